@@ -0,0 +1,298 @@
+use std::time::Duration;
+
+use bevy::audio::{PlaybackMode, Volume};
+use bevy::prelude::*;
+
+use crate::game_state::*;
+use crate::pickup::CakeEaten;
+use crate::settings::Settings;
+
+/// Cells an alien walks between footsteps
+pub const FOOTSTEP_DISTANCE: f32 = 1.0;
+
+// pure tones are loud, full volume in the settings plays them at this level
+const TONE_LEVEL: f32 = 0.3;
+
+// note frequencies for the tunes, a rest is silent
+const C4: f32 = 261.63;
+const D4: f32 = 293.66;
+const E4: f32 = 329.63;
+const F4: f32 = 349.23;
+const G4: f32 = 392.0;
+const A4: f32 = 440.0;
+const B4: f32 = 493.88;
+const C5: f32 = 523.25;
+const REST: f32 = 0.0;
+
+/// A sound effect the gameplay asks for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    Footstep,
+    EatCake,
+    /// The cake or a pickup appearing on the board
+    Spawn,
+}
+
+impl Sound {
+    // frequency and length of the tone
+    fn tone(self) -> (f32, f32) {
+        match self {
+            Sound::Footstep => (110.0, 0.05),
+            Sound::EatCake => (880.0, 0.15),
+            Sound::Spawn => (660.0, 0.3),
+        }
+    }
+}
+
+/// A sound to play from `position` on the board, sent by the gameplay systems
+#[derive(Event, Clone, Copy, Debug)]
+pub struct SoundEffect {
+    pub sound: Sound,
+    pub position: Vec3,
+}
+
+/// The tune for each part of the game, played in a loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Track {
+    Menu,
+    Playing,
+    GameOver,
+}
+
+impl Track {
+    pub fn for_state(state: &GameState) -> Track {
+        match state {
+            GameState::Menu | GameState::HighScores | GameState::Settings => Track::Menu,
+            GameState::Playing | GameState::LevelComplete => Track::Playing,
+            GameState::GameOver => Track::GameOver,
+        }
+    }
+
+    /// Frequency and length in seconds of each note
+    pub fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            Track::Menu => &[
+                (C4, 0.4),
+                (E4, 0.4),
+                (G4, 0.4),
+                (E4, 0.4),
+                (F4, 0.4),
+                (A4, 0.4),
+                (G4, 0.8),
+                (REST, 0.4),
+            ],
+            Track::Playing => &[
+                (G4, 0.2),
+                (G4, 0.2),
+                (A4, 0.2),
+                (G4, 0.2),
+                (C5, 0.2),
+                (B4, 0.4),
+                (REST, 0.2),
+                (E4, 0.2),
+                (F4, 0.2),
+                (G4, 0.2),
+                (E4, 0.2),
+                (D4, 0.4),
+                (C4, 0.4),
+            ],
+            Track::GameOver => &[
+                (G4, 0.5),
+                (F4, 0.5),
+                (E4, 0.5),
+                (D4, 0.5),
+                (C4, 1.0),
+                (REST, 1.5),
+            ],
+        }
+    }
+}
+
+/// Where the music is up to
+#[derive(Resource, Default)]
+pub struct MusicPlayer {
+    pub track: Option<Track>,
+    pub note: usize,
+    /// Counts down the note being played
+    pub timer: Timer,
+}
+
+/// A playing tone, despawned when it is over whether or not there is a device to hear it on
+#[derive(Component)]
+pub struct SoundLifetime(pub Timer);
+
+// a tone entity, `position` makes it a spatial sound
+fn spawn_tone(
+    commands: &mut Commands,
+    pitches: &mut Assets<Pitch>,
+    frequency: f32,
+    seconds: f32,
+    volume: f32,
+    position: Option<Vec3>,
+) {
+    let duration = Duration::from_secs_f32(seconds);
+    let mut entity = commands.spawn((
+        PitchBundle {
+            source: pitches.add(Pitch::new(frequency, duration)),
+            settings: PlaybackSettings {
+                mode: PlaybackMode::Once,
+                volume: Volume::new(volume * TONE_LEVEL),
+                spatial: position.is_some(),
+                ..default()
+            },
+        },
+        SoundLifetime(Timer::new(duration, TimerMode::Once)),
+    ));
+
+    if let Some(position) = position {
+        entity.insert(TransformBundle::from_transform(Transform::from_translation(
+            position,
+        )));
+    }
+}
+
+// builds without audio have no pitch assets, and then the game is silent
+pub fn has_audio(pitches: Option<Res<Assets<Pitch>>>) -> bool {
+    pitches.is_some()
+}
+
+pub fn play_sound_effects(
+    mut commands: Commands,
+    mut pitches: ResMut<Assets<Pitch>>,
+    settings: Res<Settings>,
+    mut effects: EventReader<SoundEffect>,
+    mut eaten: EventReader<CakeEaten>,
+) {
+    let cakes = eaten.read().map(|cake| (Sound::EatCake, cake.position));
+    let sounds = effects
+        .read()
+        .map(|effect| (effect.sound, effect.position))
+        .chain(cakes);
+
+    for (sound, position) in sounds {
+        let (frequency, seconds) = sound.tone();
+        spawn_tone(
+            &mut commands,
+            &mut pitches,
+            frequency,
+            seconds,
+            settings.audio.effects_volume,
+            Some(position),
+        );
+    }
+}
+
+// the next note of the tune for the current state, starting over when the state changes
+pub fn play_music(
+    mut commands: Commands,
+    mut pitches: ResMut<Assets<Pitch>>,
+    settings: Res<Settings>,
+    state: Res<State<GameState>>,
+    mut music: ResMut<MusicPlayer>,
+    time: Res<Time>,
+) {
+    let track = Track::for_state(state.get());
+    if music.track != Some(track) {
+        info!("Playing {track:?} music");
+        music.track = Some(track);
+        music.note = 0;
+        music.timer = Timer::default();
+    } else if !music.timer.tick(time.delta()).finished() {
+        return;
+    }
+
+    let notes = track.notes();
+    let (frequency, seconds) = notes[music.note % notes.len()];
+    music.note = (music.note + 1) % notes.len();
+    music.timer = Timer::from_seconds(seconds, TimerMode::Once);
+
+    if frequency != REST {
+        spawn_tone(
+            &mut commands,
+            &mut pitches,
+            frequency,
+            seconds,
+            settings.audio.music_volume,
+            None,
+        );
+    }
+}
+
+pub fn despawn_finished_sounds(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut sounds: Query<(Entity, &mut SoundLifetime)>,
+) {
+    for (entity, mut lifetime) in sounds.iter_mut() {
+        if lifetime.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    // the audio systems from the windowed game, without a device to play on
+    fn audio_app() -> App {
+        let mut app = test_app(1);
+        app.init_asset::<Pitch>()
+            .init_resource::<MusicPlayer>()
+            .add_systems(
+                Update,
+                (play_music, play_sound_effects, despawn_finished_sounds).run_if(has_audio),
+            );
+        app
+    }
+
+    #[test]
+    fn walking_makes_footsteps() {
+        let mut app = audio_app();
+        let mut reader = app.world.resource::<Events<SoundEffect>>().get_reader();
+        let mut footsteps = 0;
+
+        press(&mut app, KeyCode::ArrowUp);
+        for _ in 0..35 {
+            app.update();
+            let events = app.world.resource::<Events<SoundEffect>>();
+            footsteps += reader
+                .read(events)
+                .filter(|effect| effect.sound == Sound::Footstep)
+                .count();
+        }
+
+        // 3.5 cells walked
+        assert_eq!(footsteps, 3);
+    }
+
+    #[test]
+    fn sounds_finish_without_an_audio_device() {
+        let mut app = audio_app();
+        app.world.send_event(SoundEffect {
+            sound: Sound::Spawn,
+            position: Vec3::new(2.0, 0.0, 3.0),
+        });
+        app.world.send_event(CakeEaten {
+            position: Vec3::new(4.0, 0.0, 1.0),
+        });
+        app.update();
+
+        assert_eq!(app.world.resource::<MusicPlayer>().track, Some(Track::Playing));
+        let mut spatial = app
+            .world
+            .query_filtered::<&Transform, (With<SoundLifetime>, With<Handle<Pitch>>)>();
+        let positions: Vec<Vec3> = spatial
+            .iter(&app.world)
+            .map(|transform| transform.translation)
+            .collect();
+        assert_eq!(positions.len(), 2);
+        assert!(positions.contains(&Vec3::new(2.0, 0.0, 3.0)));
+        assert!(positions.contains(&Vec3::new(4.0, 0.0, 1.0)));
+
+        // nothing plays them, they go away on their own
+        run_frames(&mut app, 30);
+        assert_eq!(spatial.iter(&app.world).count(), 0);
+    }
+}
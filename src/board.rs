@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::BoardSize;
+use crate::pathfinding::PathCache;
+
+/// A tile position on the board, `i` runs along the world x axis and `j` along z
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cell {
+    pub i: usize,
+    pub j: usize,
+}
+
+impl Cell {
+    pub fn new(i: usize, j: usize) -> Self {
+        Cell { i, j }
+    }
+}
+
+/// What is on one tile of the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub height: f32,
+    /// Whether characters can stand on it
+    pub walkable: bool,
+}
+
+impl Tile {
+    pub fn new(height: f32) -> Self {
+        Tile {
+            height,
+            walkable: true,
+        }
+    }
+}
+
+/// The tiles of the board with bounds checked access and conversions between cells and world
+/// positions, a cell's center is at `x = i, z = j`
+#[derive(Clone, Debug)]
+pub struct Board {
+    size: BoardSize,
+    tiles: Vec<Tile>,
+    paths: PathCache,
+}
+
+// flat until spawn_game_board builds the real one
+impl Default for Board {
+    fn default() -> Self {
+        Board::new(BoardSize::default(), |_| Tile::new(0.0))
+    }
+}
+
+impl Board {
+    pub fn new(size: BoardSize, mut tile: impl FnMut(Cell) -> Tile) -> Self {
+        let tiles = (0..size.height)
+            .flat_map(|j| (0..size.width).map(move |i| Cell::new(i, j)))
+            .map(&mut tile)
+            .collect();
+        Board {
+            size,
+            tiles,
+            paths: PathCache::default(),
+        }
+    }
+
+    pub fn size(&self) -> BoardSize {
+        self.size
+    }
+
+    pub(crate) fn path_cache(&self) -> &PathCache {
+        &self.paths
+    }
+
+    pub fn contains(&self, cell: Cell) -> bool {
+        cell.i < self.size.width && cell.j < self.size.height
+    }
+
+    pub fn get(&self, cell: Cell) -> Option<&Tile> {
+        self.contains(cell)
+            .then(|| &self.tiles[cell.j * self.size.width + cell.i])
+    }
+
+    /// Every cell, row by row
+    pub fn cells(&self) -> impl Iterator<Item = Cell> {
+        let width = self.size.width;
+        (0..self.tiles.len()).map(move |index| Cell::new(index % width, index / width))
+    }
+
+    /// The tiles of each row, `j` going up
+    pub fn rows(&self) -> impl Iterator<Item = &[Tile]> {
+        self.tiles.chunks(self.size.width.max(1))
+    }
+
+    /// The cells next to `cell` along i and j that are on the board
+    pub fn neighbours(&self, cell: Cell) -> impl Iterator<Item = Cell> + '_ {
+        [(0, 1), (1, 0), (0, -1), (-1, 0)]
+            .into_iter()
+            .filter_map(move |(di, dj)| {
+                let i = cell.i.checked_add_signed(di)?;
+                let j = cell.j.checked_add_signed(dj)?;
+                Some(Cell::new(i, j))
+            })
+            .filter(|neighbour| self.contains(*neighbour))
+    }
+
+    /// The cell whose tile is under a world position, `None` off the board
+    pub fn world_to_cell(&self, position: Vec3) -> Option<Cell> {
+        let (i, j) = (position.x.round(), position.z.round());
+        if i < 0.0 || j < 0.0 {
+            return None;
+        }
+
+        let cell = Cell::new(i as usize, j as usize);
+        self.contains(cell).then_some(cell)
+    }
+
+    /// The center of a cell's tile surface
+    pub fn cell_to_world(&self, cell: Cell) -> Vec3 {
+        let height = self.get(cell).map_or(0.0, |tile| tile.height);
+        Vec3::new(cell.i as f32, height, cell.j as f32)
+    }
+
+    /// Height of the tile under a world position, the ground is at 0 off the board
+    pub fn height_at(&self, position: Vec3) -> f32 {
+        self.world_to_cell(position)
+            .and_then(|cell| self.get(cell))
+            .map_or(0.0, |tile| tile.height)
+    }
+
+    /// The nearest position on the board, for keeping things from walking off it
+    pub fn clamp(&self, position: Vec3) -> Vec3 {
+        Vec3::new(
+            position.x.clamp(0.0, self.size.max_i()),
+            position.y,
+            position.z.clamp(0.0, self.size.max_j()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn board_converts_between_cells_and_world() {
+        let board = Board::new(
+            BoardSize {
+                width: 4,
+                height: 3,
+            },
+            |cell| Tile::new(cell.i as f32 + cell.j as f32 * 10.0),
+        );
+
+        assert_eq!(board.get(Cell::new(3, 2)).unwrap().height, 23.0);
+        assert_eq!(board.get(Cell::new(4, 0)), None);
+        assert_eq!(board.get(Cell::new(0, 3)), None);
+
+        assert_eq!(board.world_to_cell(Vec3::new(1.4, 5.0, 1.6)), Some(Cell::new(1, 2)));
+        assert_eq!(board.world_to_cell(Vec3::new(-0.6, 0.0, 0.0)), None);
+        assert_eq!(board.world_to_cell(Vec3::new(0.0, 0.0, 2.6)), None);
+        assert_eq!(board.cell_to_world(Cell::new(2, 1)), Vec3::new(2.0, 12.0, 1.0));
+        assert_eq!(board.height_at(Vec3::new(2.2, 0.0, 0.9)), 12.0);
+        assert_eq!(board.height_at(Vec3::new(20.0, 0.0, 0.0)), 0.0);
+
+        let mut corner: Vec<Cell> = board.neighbours(Cell::new(0, 0)).collect();
+        corner.sort_by_key(|cell| (cell.i, cell.j));
+        assert_eq!(corner, [Cell::new(0, 1), Cell::new(1, 0)]);
+        assert_eq!(board.neighbours(Cell::new(1, 1)).count(), 4);
+        assert_eq!(board.neighbours(Cell::new(3, 2)).count(), 2);
+    }
+}
@@ -1,17 +1,274 @@
+use crate::board::{Board, Cell};
 use crate::game_state::*;
+use crate::interpolation::SimulatedTransform;
+use crate::pickup::{Pickup, PickupKinds, PickupRng, PICKUP_LIFT};
+use crate::player::follow_path;
+use crate::settings::{CakeMovement, Settings};
 use bevy::prelude::*;
-use std::f32::consts::PI;
+use rand::rngs::StdRng;
+use rand::Rng;
 
-pub fn update_cake(time: Res<Time>, mut game: ResMut<Game>, mut transforms: Query<&mut Transform>) {
-    let x = (BOARD_SIZE_I - 2.0) / 2.0;
-    let y = (BOARD_SIZE_J - 2.0) / 2.0;
+// the walking movements cover about as much ground per second as the orbit
+const WALK_SPEED_PER_RADIAN: f32 = 5.0;
 
-    game.cake.i = (time.elapsed_seconds() * 0.4).sin() * x + x + 1.0;
-    game.cake.j = (time.elapsed_seconds() * 0.4).cos() * y + y + 1.0;
+// changing the setting moves the cakes already on the board the new way, starting from wherever
+// they are
+pub fn apply_cake_movement(
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    mut movement: Local<Option<CakeMovement>>,
+) {
+    let new_movement = settings.gameplay.cake_movement;
+    // the pickups placed at startup already move the way the setting says
+    let Some(old_movement) = movement.replace(new_movement) else {
+        return;
+    };
+    if old_movement == new_movement {
+        return;
+    }
+
+    info!("Cake movement {old_movement:?} -> {new_movement:?}");
+    let moving = game.pickups.iter_mut().filter(|pickup| kinds.0[pickup.kind].moves);
+    for pickup in moving {
+        pickup.movement = new_movement;
+        pickup.path.clear();
+    }
+}
+
+// the loop a patrolling cake walks, two tiles in from the corners
+fn patrol_route(board: &Board) -> [Cell; 4] {
+    let size = board.size();
+    let (near_i, near_j) = (2.min(size.width - 1), 2.min(size.height - 1));
+    let far_i = size.width.saturating_sub(3).max(near_i);
+    let far_j = size.height.saturating_sub(3).max(near_j);
+    [
+        Cell::new(near_i, near_j),
+        Cell::new(far_i, near_j),
+        Cell::new(far_i, far_j),
+        Cell::new(near_i, far_j),
+    ]
+}
 
-    *transforms.get_mut(game.cake.entity.unwrap()).unwrap() = Transform {
-        translation: Vec3::new(game.cake.i, 0.4, game.cake.j),
-        // rotation: Quat::from_rotation_y(rotation),
-        ..default()
+// the next cells for the walking movements to follow
+fn plan_cake_path(
+    board: &Board,
+    players: &[Player],
+    cake: &mut Pickup,
+    movement: CakeMovement,
+    rng: &mut StdRng,
+) -> Vec<Cell> {
+    let Some(here) = board.world_to_cell(cake.position()) else {
+        return Vec::new();
     };
+
+    match movement {
+        CakeMovement::Patrol => {
+            let route = patrol_route(board);
+            if route[cake.waypoint % route.len()] == here {
+                cake.waypoint = (cake.waypoint + 1) % route.len();
+            }
+            board
+                .find_path(here, route[cake.waypoint % route.len()])
+                .unwrap_or_default()
+        }
+        // one step at a time to the walkable neighbour furthest from the nearest alien
+        CakeMovement::Flee => {
+            let distance_to_aliens = |cell: Cell| {
+                players
+                    .iter()
+                    .map(|player| {
+                        (player.i - cell.i as f32).abs() + (player.j - cell.j as f32).abs()
+                    })
+                    .fold(f32::INFINITY, f32::min)
+            };
+
+            board
+                .neighbours(here)
+                .filter(|neighbour| board.step_cost(here, *neighbour).is_some())
+                .max_by(|a, b| distance_to_aliens(*a).total_cmp(&distance_to_aliens(*b)))
+                .filter(|neighbour| distance_to_aliens(*neighbour) > distance_to_aliens(here))
+                .map(|neighbour| vec![here, neighbour])
+                .unwrap_or_default()
+        }
+        CakeMovement::Wander => {
+            let size = board.size();
+            let target = Cell::new(rng.gen_range(0..size.width), rng.gen_range(0..size.height));
+            board.find_path(here, target).unwrap_or_default()
+        }
+        CakeMovement::Stationary | CakeMovement::Orbit => Vec::new(),
+    }
+}
+
+// the pickups that move each get about the board their own way, runs once per gameplay tick
+pub fn move_pickups(
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    mut rng: ResMut<PickupRng>,
+    mut simulated_query: Query<&mut SimulatedTransform>,
+    time: Res<Time>,
+) {
+    let game = &mut *game;
+    let speed = settings.gameplay.difficulty.cake_speed();
+
+    let moving = game.pickups.iter_mut().filter(|pickup| kinds.0[pickup.kind].moves);
+    for cake in moving {
+        match cake.movement {
+            CakeMovement::Stationary => {}
+            CakeMovement::Orbit => {
+                let board_size = game.board.size();
+                let x = (board_size.width as f32 - 2.0) / 2.0;
+                let y = (board_size.height as f32 - 2.0) / 2.0;
+
+                let angle = game.elapsed.as_secs_f32() * speed;
+
+                cake.i = angle.sin() * x + x + 1.0;
+                cake.j = angle.cos() * y + y + 1.0;
+            }
+            CakeMovement::Patrol | CakeMovement::Flee | CakeMovement::Wander => {
+                if cake.path.is_empty() {
+                    let movement = cake.movement;
+                    cake.path =
+                        plan_cake_path(&game.board, &game.players, cake, movement, &mut rng.0);
+                }
+
+                let step = speed * WALK_SPEED_PER_RADIAN * time.delta_seconds();
+                let mut position = cake.position();
+                follow_path(&mut position, &mut 0.0, &mut cake.path, step);
+                cake.i = position.x;
+                cake.j = position.z;
+            }
+        }
+
+        // ride on the tiles
+        let Some(Ok(mut simulated)) = cake.entity.map(|entity| simulated_query.get_mut(entity))
+        else {
+            continue;
+        };
+        simulated.current.translation = Vec3::new(
+            cake.i,
+            game.board.height_at(cake.position()) + PICKUP_LIFT,
+            cake.j,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Difficulty;
+    use crate::test_support::*;
+
+    #[test]
+    fn difficulty_sets_the_cake_speed() {
+        let cake_after_a_second = |difficulty| {
+            let mut app = test_app(1);
+            app.world.resource_mut::<Settings>().gameplay.difficulty = difficulty;
+            run_frames(&mut app, 60);
+            cake_position(&app)
+        };
+
+        assert_ne!(
+            cake_after_a_second(Difficulty::Easy),
+            cake_after_a_second(Difficulty::Hard)
+        );
+    }
+
+    #[test]
+    fn cake_movements_follow_the_settings() {
+        let mut app = cake_app(CakeMovement::Stationary);
+        let start = cake_position(&app);
+        run_frames(&mut app, 60);
+        assert_eq!(cake_position(&app), start);
+
+        for movement in [CakeMovement::Patrol, CakeMovement::Wander] {
+            let mut app = cake_app(movement);
+            let start = cake_position(&app);
+            run_frames(&mut app, 120);
+            assert_ne!(cake_position(&app), start, "{movement:?}");
+        }
+    }
+
+    #[test]
+    fn each_cake_keeps_its_own_movement() {
+        let mut app = cake_app(CakeMovement::Stationary);
+        // the setting is taken up by the cake already on the board
+        run_frames(&mut app, 1);
+        let kind = pickup_kind(app.world.resource::<PickupKinds>(), "cake");
+        let mut orbiting = Pickup::new(kind, Cell::new(1, 1));
+        orbiting.movement = CakeMovement::Orbit;
+        app.world.resource_mut::<Game>().pickups.push(orbiting);
+        let start = cake_position(&app);
+
+        run_frames(&mut app, 60);
+
+        let game = app.world.resource::<Game>();
+        let cakes: Vec<&Pickup> =
+            game.pickups.iter().filter(|pickup| pickup.kind == kind).collect();
+        assert_eq!(cakes.len(), 2);
+        assert_eq!(cakes[0].movement, CakeMovement::Stationary);
+        assert_eq!((cakes[0].i, cakes[0].j), start);
+        assert_ne!((cakes[1].i, cakes[1].j), (1.0, 1.0));
+
+        // changing the setting moves them all the new way
+        app.world.resource_mut::<Settings>().gameplay.cake_movement = CakeMovement::Wander;
+        run_frames(&mut app, 1);
+        let game = app.world.resource::<Game>();
+        assert!(game.pickups.iter().all(|pickup| pickup.movement == CakeMovement::Wander));
+    }
+
+    #[test]
+    fn fleeing_cake_keeps_away_from_the_alien() {
+        let mut app = cake_app(CakeMovement::Flee);
+        let (i, j) = player_position(&app, 0);
+        move_cake(&mut app, i + 2.0, j);
+
+        run_frames(&mut app, 120);
+
+        let (cake_i, cake_j) = cake_position(&app);
+        assert!((cake_i - i).abs() + (cake_j - j).abs() > 2.0, "cake at {cake_i}, {cake_j}");
+    }
+
+    #[test]
+    fn cake_rides_on_the_tiles() {
+        let mut app = cake_app(CakeMovement::Patrol);
+        run_frames(&mut app, 90);
+
+        let game = app.world.resource::<Game>();
+        let cake = cake(&app).unwrap();
+        let simulated = app.world.get::<SimulatedTransform>(cake.entity.unwrap()).unwrap();
+        assert_eq!(
+            simulated.current.translation.y,
+            game.board.height_at(cake.position()) + PICKUP_LIFT
+        );
+    }
+
+    #[test]
+    fn eaten_cake_respawns() {
+        let mut app = cake_app(CakeMovement::Stationary);
+        let (i, j) = player_position(&app, 0);
+        move_cake(&mut app, i + 1.0, j);
+        let eaten = cake(&app).unwrap().entity.unwrap();
+
+        press(&mut app, KeyCode::ArrowUp);
+        run_frames(&mut app, 10);
+        release_all(&mut app);
+
+        let game = app.world.resource::<Game>();
+        assert_eq!((game.players[0].score, game.players[0].cake_eaten), (2, 1));
+        assert!(cake(&app).is_none());
+        assert!(app.world.get_entity(eaten).is_none());
+
+        // the cake stays away for its respawn time, nothing breaks in the meantime
+        run_frames(&mut app, 240);
+        assert!(cake(&app).is_none());
+
+        run_frames(&mut app, 120);
+        let game = app.world.resource::<Game>();
+        let cake = cake(&app).expect("cake should be back");
+        assert!(app.world.get::<SimulatedTransform>(cake.entity.unwrap()).is_some());
+        assert!(game.board.size().contains(cake.i, cake.j));
+        assert_eq!(game.players[0].cake_eaten, 1);
+    }
 }
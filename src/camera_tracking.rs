@@ -1,18 +1,23 @@
 #![allow(clippy::assign_op_pattern)]
+#![allow(clippy::too_many_arguments)]
 #![allow(dead_code)]
 
 // use bevy::input::keyboard::KeyboardInput;
 use bevy::input::mouse::{MouseMotion, MouseWheel};
 use bevy::prelude::*;
-use bevy::render::camera::Projection;
+use bevy::render::camera::{Projection, Viewport};
 use std::f32::consts::PI;
 
 use crate::game_state::Game;
+use crate::picking::CursorPosition;
+use crate::settings::Settings;
 use bevy::window::*;
 
 /// Tags an entity as tracking camera
 #[derive(Component)]
 pub struct TrackingCamera {
+    /// Index into `Game.players` of the character this camera follows
+    pub player: usize,
     /// The "focus point" to orbit around. It is automatically updated when panning the camera
     pub focus: Vec3,
     pub radius: f32,
@@ -22,6 +27,7 @@ pub struct TrackingCamera {
 impl Default for TrackingCamera {
     fn default() -> Self {
         TrackingCamera {
+            player: 0,
             focus: Vec3::ZERO,
             radius: 5.0,
             upside_down: false,
@@ -32,13 +38,15 @@ impl Default for TrackingCamera {
 /// Pan the camera with middle mouse click, zoom with scroll wheel, orbit with right mouse click.
 pub fn update_tracking_camera(
     primary_query: Query<&Window, With<PrimaryWindow>>,
+    cursor: Res<CursorPosition>,
     mut ev_mouse_motion: EventReader<MouseMotion>,
     mut ev_mouse_scroll: EventReader<MouseWheel>,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
     input_mouse: Res<ButtonInput<MouseButton>>,
     input_keyboard: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut TrackingCamera, &mut Transform, &Projection)>,
+    mut query: Query<(&mut TrackingCamera, &mut Transform, &Projection, &Camera)>,
+    targets: Query<&Transform, Without<TrackingCamera>>,
     game: Res<Game>,
+    settings: Res<Settings>,
 ) {
     let Ok(primary) = primary_query.get_single() else {
         return;
@@ -55,24 +63,38 @@ pub fn update_tracking_camera(
 
     if input_mouse.pressed(orbit_button) && !input_keyboard.pressed(KeyCode::ShiftLeft) {
         for mouse_motion in ev_mouse_motion.read() {
-            rotation_move += mouse_motion.delta;
+            rotation_move += mouse_motion.delta * settings.input.mouse_sensitivity;
         }
     } else if input_mouse.pressed(orbit_button) && input_keyboard.pressed(KeyCode::ShiftLeft) {
         // Pan only if we're not rotating at the moment
         for mouse_motion in ev_mouse_motion.read() {
-            pan += mouse_motion.delta * 2.0;
+            pan += mouse_motion.delta * 2.0 * settings.input.mouse_sensitivity;
         }
     }
 
     for mouse_wheel in ev_mouse_scroll.read() {
-        scroll += mouse_wheel.y * 0.05;
+        scroll += mouse_wheel.y * 0.05 * settings.input.zoom_sensitivity;
     }
 
     if input_mouse.just_released(orbit_button) || input_mouse.just_pressed(orbit_button) {
         orbit_button_changed = true;
     }
 
-    for (mut tracking_camera, mut transform, projection) in query.iter_mut() {
+    // in split screen the mouse only steers the camera whose viewport is under the cursor
+    let cursor = cursor
+        .0
+        .map(|position| position * primary.scale_factor());
+
+    for (mut tracking_camera, mut transform, projection, camera) in query.iter_mut() {
+        let under_cursor = match (&camera.viewport, cursor) {
+            (Some(viewport), Some(cursor)) => {
+                let min = viewport.physical_position.as_vec2();
+                let max = min + viewport.physical_size.as_vec2();
+                cursor.cmpge(min).all() && cursor.cmplt(max).all()
+            }
+            _ => true,
+        };
+
         if orbit_button_changed {
             // only check for upside down when orbiting started or ended this frame
             // if the camera is "upside" down, panning horizontally would be inverted, so invert the input to make it correct
@@ -82,6 +104,12 @@ pub fn update_tracking_camera(
 
         let mut any = false;
 
+        let (rotation_move, mut pan, scroll) = if under_cursor {
+            (rotation_move, pan, scroll)
+        } else {
+            (Vec2::ZERO, Vec2::ZERO, 0.0)
+        };
+
         if rotation_move.length_squared() > 0.0 {
             any = true;
 
@@ -149,13 +177,18 @@ pub fn update_tracking_camera(
                 + rot_matrix.mul_vec3(Vec3::new(0.0, 0.0, tracking_camera.radius));
         }
 
-        if keyboard_input.any_pressed(vec![
-            KeyCode::ArrowUp,
-            KeyCode::ArrowDown,
-            KeyCode::ArrowRight,
-            KeyCode::ArrowLeft,
-        ]) {
-            let target = Vec3::new(game.player.i, 1., game.player.j);
+        let Some(player) = game.players.get(tracking_camera.player) else {
+            continue;
+        };
+
+        if player.input.any() || !player.path.is_empty() {
+            // follow the interpolated alien rather than its last gameplay tick
+            let position = player
+                .entity
+                .and_then(|entity| targets.get(entity).ok())
+                .map_or(Vec3::new(player.i, 0., player.j), |target| target.translation);
+            // look a little above the tile the alien is standing on
+            let target = Vec3::new(position.x, game.board.height_at(position) + 1., position.z);
             tracking_camera.focus = target;
 
             transform.rotation = look_to(target - transform.translation, Vec3::Y);
@@ -168,6 +201,37 @@ pub fn update_tracking_camera(
     }
 }
 
+/// Give each tracking camera its own vertical slice of the window
+pub fn set_camera_viewports(
+    windows: Query<&Window>,
+    mut resize_events: EventReader<WindowResized>,
+    mut query: Query<&mut Camera, With<TrackingCamera>>,
+) {
+    // only resize when the window size changes, the first event arrives right after startup
+    for resize_event in resize_events.read() {
+        let Ok(window) = windows.get(resize_event.window) else {
+            continue;
+        };
+
+        let camera_count = query.iter().count() as u32;
+        if camera_count < 2 {
+            continue;
+        }
+
+        let width = window.resolution.physical_width() / camera_count;
+        let height = window.resolution.physical_height();
+
+        // cameras are ordered by local player slot, see spawn_camera
+        for mut camera in query.iter_mut() {
+            camera.viewport = Some(Viewport {
+                physical_position: UVec2::new(camera.order as u32 * width, 0),
+                physical_size: UVec2::new(width, height),
+                ..default()
+            });
+        }
+    }
+}
+
 pub fn look_to(direction: Vec3, up: Vec3) -> Quat {
     let back = -direction.try_normalize().unwrap_or(Vec3::NEG_Z);
     let up = up.try_normalize().unwrap_or(Vec3::Y);
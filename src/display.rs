@@ -1,17 +1,45 @@
 use crate::game_state::*;
+use crate::level::CurrentLevel;
+use crate::pickup::effects_text;
 use bevy::prelude::*;
 
 // update the score displayed during the game
-pub fn update_display(time: Res<Time>, game: Res<Game>, mut query: Query<&mut Text>) {
+pub fn update_display(
+    game: Res<Game>,
+    level: Option<Res<CurrentLevel>>,
+    mode: Option<Res<GameMode>>,
+    round_length: Option<Res<RoundLength>>,
+    mut query: Query<&mut Text>,
+) {
     // info!("Updating display");
 
     if let Ok(mut text) = query.get_single_mut() {
-        text.sections[0].value = format!(
-            "time: {}\nposition: {}, {}\nrotation: {}",
-            time.elapsed_seconds(),
-            game.player.i,
-            game.player.j,
-            game.player.rotation
-        );
+        let mut value = match time_left(&game, round_length.as_deref()) {
+            Some(left) => format!("time left: {:.1}", left.as_secs_f32()),
+            None => format!("time: {}", game.elapsed.as_secs_f32()),
+        };
+
+        // objectives only count when playing the levels
+        if let (Some(level), Some(GameMode::Levels)) = (&level, mode.as_deref()) {
+            value += &format!("\n{}", level.progress_text(&game));
+        }
+
+        for (index, player) in game.players.iter().enumerate() {
+            value += &format!(
+                "\nplayer {}: score: {} cakes: {}\nposition: {}, {}\nrotation: {}",
+                index + 1,
+                player.score,
+                player.cake_eaten,
+                player.i,
+                player.j,
+                player.rotation
+            );
+
+            if !player.effects.is_empty() {
+                value += &format!("\neffects: {}", effects_text(player));
+            }
+        }
+
+        text.sections[0].value = value;
     }
 }
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Cell};
+use crate::game_state::*;
+use crate::interpolation::SimulatedTransform;
+use crate::player::follow_path;
+use crate::scoring::ScorePopup;
+
+/// Board cells a creature walks per second, slower than the aliens so they can get away
+pub const ENEMY_SPEED: f32 = 3.0;
+
+/// How far a creature sees an alien, in cells
+pub const SIGHT_RADIUS: f32 = 4.0;
+
+/// Points an alien loses when a creature catches it
+pub const CONTACT_PENALTY: i32 = 3;
+
+// closer than this along both axes is a catch
+const CONTACT_DISTANCE: f32 = 0.5;
+
+// after a catch the creature runs off for a while instead of catching the same alien every tick
+const FLEE_TIME: Duration = Duration::from_secs(3);
+
+// how far from where it stands a wandering creature picks its next spot
+const WANDER_RADIUS: usize = 4;
+
+/// How many creatures roam the board, none unless the mode asks for them
+#[derive(Resource, Default)]
+pub struct EnemyCount(pub usize);
+
+/// Randomness for the creatures, seeded from the board so replays and headless runs repeat
+#[derive(Resource)]
+pub struct EnemyRng(pub StdRng);
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Behaviour {
+    /// Walk to random spots nearby
+    Wander,
+    /// Walk between the cells of `route` in order, `next` is the one being walked to
+    Patrol { route: Vec<Cell>, next: usize },
+    /// Go after `game.players[player]`
+    Chase { player: usize },
+    /// Keep away from `game.players[player]` until the game clock reaches `until`
+    Flee { player: usize, until: Duration },
+}
+
+/// A hostile creature, it goes about its `routine` until an alien comes into sight
+pub struct Enemy {
+    pub entity: Option<Entity>,
+    pub i: f32,
+    pub j: f32,
+    pub rotation: f32,
+    pub behaviour: Behaviour,
+    /// What it does when no alien is in sight, wandering or patrolling
+    pub routine: Behaviour,
+    /// Cells still to walk through, the next one first
+    pub path: Vec<Cell>,
+}
+
+impl Enemy {
+    pub fn new(cell: Cell, routine: Behaviour) -> Self {
+        Enemy {
+            entity: None,
+            i: cell.i as f32,
+            j: cell.j as f32,
+            rotation: 0.0,
+            behaviour: routine.clone(),
+            routine,
+            path: Vec::new(),
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        Vec3::new(self.i, 0.0, self.j)
+    }
+}
+
+/// Where a creature starts and what it does, they take the corners in turn and every other
+/// one patrols a loop just inside the edge of the board
+pub fn enemy_start(index: usize, board: &Board) -> Enemy {
+    let size = board.size();
+    let (last_i, last_j) = (size.width - 1, size.height - 1);
+    let corners = [
+        Cell::new(0, 0),
+        Cell::new(last_i, last_j),
+        Cell::new(last_i, 0),
+        Cell::new(0, last_j),
+    ];
+    let start = corners[index % corners.len()];
+
+    if index % 2 == 1 {
+        let route = vec![
+            Cell::new(1, 1),
+            Cell::new(last_i - 1, 1),
+            Cell::new(last_i - 1, last_j - 1),
+            Cell::new(1, last_j - 1),
+        ];
+        // start with the route corner next to the starting one
+        let next = (0..route.len())
+            .min_by_key(|&index| route[index].i.abs_diff(start.i) + route[index].j.abs_diff(start.j))
+            .unwrap_or(0);
+        return Enemy::new(start, Behaviour::Patrol { route, next });
+    }
+
+    Enemy::new(start, Behaviour::Wander)
+}
+
+// the closest alien within sight
+fn player_in_sight(players: &[Player], position: Vec3) -> Option<usize> {
+    players
+        .iter()
+        .enumerate()
+        .map(|(index, player)| (index, Vec3::new(player.i, 0.0, player.j).distance(position)))
+        .filter(|(_, distance)| *distance <= SIGHT_RADIUS)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(index, _)| index)
+}
+
+// the next cells to walk, an empty path means stand still this tick
+fn plan_path(enemy: &mut Enemy, board: &Board, players: &[Player], rng: &mut StdRng) -> Vec<Cell> {
+    let Some(here) = board.world_to_cell(enemy.position()) else {
+        return Vec::new();
+    };
+    let player_cell = |player: usize| {
+        players
+            .get(player)
+            .and_then(|player| board.world_to_cell(Vec3::new(player.i, 0.0, player.j)))
+    };
+
+    match &mut enemy.behaviour {
+        Behaviour::Wander => {
+            let target = Cell::new(
+                rng.gen_range(here.i.saturating_sub(WANDER_RADIUS)..=here.i + WANDER_RADIUS),
+                rng.gen_range(here.j.saturating_sub(WANDER_RADIUS)..=here.j + WANDER_RADIUS),
+            );
+            board.find_path(here, target).unwrap_or_default()
+        }
+        Behaviour::Patrol { route, next } => {
+            if route.get(*next) == Some(&here) {
+                *next = (*next + 1) % route.len();
+            }
+            route
+                .get(*next)
+                .and_then(|target| board.find_path(here, *target))
+                .unwrap_or_default()
+        }
+        // one cell at a time, so the chase follows the alien as it moves
+        Behaviour::Chase { player } => player_cell(*player)
+            .and_then(|target| board.find_path(here, target))
+            .map(|path| path.into_iter().take(2).collect())
+            .unwrap_or_default(),
+        // the walkable neighbour furthest from the alien, if it is further than here
+        Behaviour::Flee { player, .. } => {
+            let Some(threat) = player_cell(*player) else {
+                return Vec::new();
+            };
+            let distance = |cell: Cell| cell.i.abs_diff(threat.i) + cell.j.abs_diff(threat.j);
+
+            board
+                .neighbours(here)
+                .filter(|neighbour| board.step_cost(here, *neighbour).is_some())
+                .max_by_key(|neighbour| distance(*neighbour))
+                .filter(|neighbour| distance(*neighbour) > distance(here))
+                .map(|neighbour| vec![here, neighbour])
+                .unwrap_or_default()
+        }
+    }
+}
+
+// decide what every creature does, move it and see whether it caught anyone, runs once per
+// gameplay tick after the aliens have moved
+pub fn update_enemies(
+    mut game: ResMut<Game>,
+    mut rng: ResMut<EnemyRng>,
+    mut simulated_query: Query<&mut SimulatedTransform>,
+    time: Res<Time>,
+    mut popups: EventWriter<ScorePopup>,
+) {
+    let move_step = ENEMY_SPEED * time.delta_seconds();
+    let game = &mut *game;
+
+    for enemy in game.enemies.iter_mut() {
+        let fleeing = matches!(enemy.behaviour, Behaviour::Flee { until, .. } if game.elapsed < until);
+
+        if !fleeing {
+            let behaviour = match player_in_sight(&game.players, enemy.position()) {
+                Some(player) => Behaviour::Chase { player },
+                // carry on, a patrol keeps its place in the route
+                None if matches!(enemy.behaviour, Behaviour::Wander | Behaviour::Patrol { .. }) => {
+                    enemy.behaviour.clone()
+                }
+                None => enemy.routine.clone(),
+            };
+
+            if enemy.behaviour != behaviour {
+                info!("Creature behaviour {:?} -> {:?}", enemy.behaviour, behaviour);
+                // pick the patrol up where it was left
+                if let Behaviour::Patrol { .. } = enemy.behaviour {
+                    enemy.routine = enemy.behaviour.clone();
+                }
+                enemy.behaviour = behaviour;
+                enemy.path.clear();
+            }
+        }
+
+        if enemy.path.is_empty() {
+            enemy.path = plan_path(enemy, &game.board, &game.players, &mut rng.0);
+        }
+
+        let mut position = enemy.position();
+        if follow_path(&mut position, &mut enemy.rotation, &mut enemy.path, move_step) {
+            enemy.i = position.x;
+            enemy.j = position.z;
+
+            let transform = Transform {
+                translation: Vec3::new(enemy.i, game.board.height_at(position), enemy.j),
+                rotation: Quat::from_rotation_y(enemy.rotation),
+                ..default()
+            };
+            if let Some(mut simulated) = enemy.entity.and_then(|entity| simulated_query.get_mut(entity).ok()) {
+                simulated.current = transform;
+            }
+        }
+
+        if fleeing {
+            continue;
+        }
+
+        // caught! the alien loses points and the creature backs off
+        let caught = game.players.iter().position(|player| {
+            !player.shielded()
+                && (player.i - enemy.i).abs() < CONTACT_DISTANCE
+                && (player.j - enemy.j).abs() < CONTACT_DISTANCE
+        });
+        if let Some(index) = caught {
+            info!("Player {} caught by a creature", index + 1);
+            let player = &mut game.players[index];
+            player.penalise(CONTACT_PENALTY);
+            let position = Vec3::new(player.i, 0.0, player.j);
+            popups.send(ScorePopup {
+                position: position + Vec3::Y * (game.board.height_at(position) + 1.0),
+                points: -CONTACT_PENALTY,
+            });
+            enemy.behaviour = Behaviour::Flee {
+                player: index,
+                until: game.elapsed + FLEE_TIME,
+            };
+            enemy.path.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn enemy_app(enemy_count: usize) -> App {
+        let mut app = test_app_without_startup(1);
+        app.insert_resource(EnemyCount(enemy_count));
+        app.update();
+        app
+    }
+
+    #[test]
+    fn creatures_wander_and_patrol_out_of_sight() {
+        let mut app = enemy_app(2);
+        let starts: Vec<(f32, f32)> = app
+            .world
+            .resource::<Game>()
+            .enemies
+            .iter()
+            .map(|enemy| (enemy.i, enemy.j))
+            .collect();
+        assert_eq!(starts, vec![(0.0, 0.0), (13.0, 20.0)]);
+
+        run_frames(&mut app, 120);
+
+        let game = app.world.resource::<Game>();
+        assert_eq!(game.enemies[0].behaviour, Behaviour::Wander);
+        assert!(matches!(game.enemies[1].behaviour, Behaviour::Patrol { .. }));
+        assert_ne!((game.enemies[1].i, game.enemies[1].j), starts[1]);
+        assert_eq!(game.players[0].score, 0);
+    }
+
+    #[test]
+    fn creatures_chase_catch_and_flee() {
+        let mut app = enemy_app(1);
+        let (i, j) = player_position(&app, 0);
+        {
+            let mut game = app.world.resource_mut::<Game>();
+            game.enemies[0].i = i;
+            game.enemies[0].j = j + 3.0;
+        }
+
+        run_frames(&mut app, 2);
+        assert_eq!(
+            app.world.resource::<Game>().enemies[0].behaviour,
+            Behaviour::Chase { player: 0 }
+        );
+
+        run_frames(&mut app, 90);
+        let game = app.world.resource::<Game>();
+        assert_eq!(game.players[0].score, -CONTACT_PENALTY);
+        assert!(matches!(game.enemies[0].behaviour, Behaviour::Flee { player: 0, .. }));
+        let enemy = &game.enemies[0];
+        assert!(
+            (enemy.i - i).abs() + (enemy.j - j).abs() >= 1.0,
+            "fled to {}, {}",
+            enemy.i,
+            enemy.j
+        );
+    }
+}
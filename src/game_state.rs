@@ -1,17 +1,124 @@
+use crate::board::{Board, Cell};
+use crate::enemy::Enemy;
+use crate::pickup::{ActiveEffect, Pickup};
+use crate::scoring::Scoring;
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Default, States)]
 pub enum GameState {
+    /// Title screen of the windowed game
+    Menu,
     #[default]
     Playing,
+    /// Round over, players type their names for the high scores
     GameOver,
+    /// Every objective of the level is met, waiting to go on to the next one
+    LevelComplete,
+    HighScores,
+    Settings,
 }
 
+/// Length of one gameplay tick (60 per second), movement, the cake and scoring run in `FixedUpdate`
+pub const TICK_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// How long a round lasts before the game is over, rounds never end without it
 #[derive(Resource)]
-pub struct CakeSpawnTimer(pub Timer);
+pub struct RoundLength(pub Duration);
+
+/// How the windowed game is played, picked on the title screen
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GameMode {
+    /// Through the levels, two minutes for each
+    #[default]
+    Levels,
+    /// A minute on the clock, every cake adds more
+    TimeAttack,
+    /// No clock, the round goes on until it is ended
+    Endless,
+}
+
+impl GameMode {
+    pub fn round_length(self) -> Option<Duration> {
+        match self {
+            GameMode::Levels => Some(Duration::from_secs(120)),
+            GameMode::TimeAttack => Some(Duration::from_secs(60)),
+            GameMode::Endless => None,
+        }
+    }
+
+    /// Time added to the round for each cake eaten
+    pub fn cake_bonus(self) -> Duration {
+        match self {
+            GameMode::TimeAttack => Duration::from_secs(5),
+            GameMode::Levels | GameMode::Endless => Duration::ZERO,
+        }
+    }
+}
 
-pub struct Cell {
-    pub(crate) height: f32,
+/// Switch to `mode`, setting up its round length
+pub fn start_mode(commands: &mut Commands, mode: GameMode) {
+    info!("Playing {mode:?}");
+
+    commands.insert_resource(mode);
+    match mode.round_length() {
+        Some(length) => commands.insert_resource(RoundLength(length)),
+        None => commands.remove_resource::<RoundLength>(),
+    }
+}
+
+/// Time left in the round, `None` when rounds don't end
+pub fn time_left(game: &Game, round_length: Option<&RoundLength>) -> Option<Duration> {
+    round_length.map(|length| (length.0 + game.time_bonus).saturating_sub(game.elapsed))
+}
+
+/// Number of local players sharing the board, each with their own camera and controls
+#[derive(Resource)]
+pub struct PlayerCount(pub usize);
+
+/// Seed for the board heights, shared with network clients so they build the same board
+#[derive(Resource)]
+pub struct BoardSeed(pub u64);
+
+/// Keys used to move a player around the board
+#[derive(Clone, Copy, Debug)]
+pub struct PlayerControls {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+}
+
+impl PlayerControls {
+    pub const ARROWS: PlayerControls = PlayerControls {
+        up: KeyCode::ArrowUp,
+        down: KeyCode::ArrowDown,
+        left: KeyCode::ArrowLeft,
+        right: KeyCode::ArrowRight,
+    };
+
+    pub const WASD: PlayerControls = PlayerControls {
+        up: KeyCode::KeyW,
+        down: KeyCode::KeyS,
+        left: KeyCode::KeyA,
+        right: KeyCode::KeyD,
+    };
+}
+
+/// The movement a player wants this frame, from the keyboard or from a network client
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl PlayerInput {
+    pub fn any(&self) -> bool {
+        self.up || self.down || self.left || self.right
+    }
 }
 
 #[derive(Default)]
@@ -20,30 +127,92 @@ pub struct Player {
     pub i: f32,
     pub j: f32,
     pub rotation: f32,
-    pub move_cooldown: Timer,
+    /// Keyboard mapping for locally controlled players, `None` for remote players
+    pub controls: Option<PlayerControls>,
+    pub input: PlayerInput,
+    /// Cells still to walk through after a click, the next one first
+    pub path: Vec<Cell>,
+    /// Timed effects from pickups
+    pub effects: Vec<ActiveEffect>,
+    /// Distance walked since the last footstep
+    pub stride: f32,
+    pub score: i32,
+    /// Combo, multiplier and where the score came from
+    pub scoring: Scoring,
+    pub cake_eaten: u32,
 }
 
-#[derive(Default)]
-pub struct Cake {
-    pub(crate) entity: Option<Entity>,
-    pub(crate) i: f32,
-    pub(crate) j: f32,
-    pub(crate) handle: Handle<Scene>,
+impl Player {
+    pub fn new(i: f32, j: f32, controls: Option<PlayerControls>) -> Self {
+        Player {
+            i,
+            j,
+            controls,
+            ..default()
+        }
+    }
 }
 
 #[derive(Resource, Default)]
 pub struct Game {
-    pub board: Vec<Vec<Cell>>,
-    pub player: Player,
-    pub cake: Cake,
-    pub score: i32,
-    pub cake_eaten: u32,
+    pub board: Board,
+    pub players: Vec<Player>,
+    pub enemies: Vec<Enemy>,
+    pub pickups: Vec<Pickup>,
+    /// Extra time for this round from pickups
+    pub time_bonus: Duration,
+    /// Time played in this session, advanced by the gameplay ticks and kept in saves
+    pub elapsed: Duration,
     pub camera_should_focus: Vec3,
     pub camera_is_focus: Vec3,
 }
 
-pub const BOARD_SIZE_I: f32 = 14.0;
-pub const BOARD_SIZE_J: f32 = 21.0;
+/// Size of the board in tiles, `width` along i and `height` along j
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoardSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BoardSize {
+            width: 14,
+            height: 21,
+        }
+    }
+}
+
+impl BoardSize {
+    // the cake circles inside the outer tiles, so it needs at least one more in the middle
+    const MIN: usize = 3;
+    const MAX: usize = 256;
+
+    /// Keeps hand edited sizes within what the game can handle
+    pub fn clamped(self) -> BoardSize {
+        BoardSize {
+            width: self.width.clamp(BoardSize::MIN, BoardSize::MAX),
+            height: self.height.clamp(BoardSize::MIN, BoardSize::MAX),
+        }
+    }
+
+    pub fn max_i(self) -> f32 {
+        (self.width - 1) as f32
+    }
+
+    pub fn max_j(self) -> f32 {
+        (self.height - 1) as f32
+    }
+
+    pub fn center(self) -> (f32, f32) {
+        (self.width as f32 / 2.0, self.height as f32 / 2.0)
+    }
+
+    pub fn contains(self, i: f32, j: f32) -> bool {
+        (0.0..=self.max_i()).contains(&i) && (0.0..=self.max_j()).contains(&j)
+    }
+}
 
 // pub const RESET_FOCUS: [f32; 3] = [
 //     BOARD_SIZE_I / 2.0,
@@ -51,13 +220,94 @@ pub const BOARD_SIZE_J: f32 = 21.0;
 //     BOARD_SIZE_J / 2.0 - 0.5,
 // ];
 
-pub fn setup_game_state(mut game: ResMut<Game>) {
-    info!("Setting up game state");
+pub fn setup_game_state(
+    mut game: ResMut<Game>,
+    player_count: Res<PlayerCount>,
+    board_size: Res<BoardSize>,
+) {
+    info!("Setting up game state for {} player(s)", player_count.0);
 
     // reset the game state
-    game.cake_eaten = 0;
-    game.score = 0;
-    game.player.i = BOARD_SIZE_I / 2.0;
-    game.player.j = BOARD_SIZE_J / 2.0;
-    game.player.move_cooldown = Timer::from_seconds(0.01, TimerMode::Once);
+    game.players = (0..player_count.0)
+        .map(|index| {
+            let controls = if index == 0 {
+                PlayerControls::ARROWS
+            } else {
+                PlayerControls::WASD
+            };
+            let (i, j) = start_position(index, player_count.0, *board_size);
+            Player::new(i, j, Some(controls))
+        })
+        .collect();
+}
+
+/// Where a player starts, the players are spread along the j axis
+pub fn start_position(index: usize, player_count: usize, board_size: BoardSize) -> (f32, f32) {
+    (
+        board_size.center().0,
+        (board_size.height as f32 * (index + 1) as f32 / (player_count + 1) as f32).round(),
+    )
+}
+
+// runs in FixedUpdate, so the clock moves one tick at a time and only while playing
+pub fn advance_game_clock(time: Res<Time>, mut game: ResMut<Game>) {
+    game.elapsed += time.delta();
+}
+
+pub fn check_round_over(
+    game: Res<Game>,
+    round_length: Res<RoundLength>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if game.elapsed >= round_length.0 + game.time_bonus {
+        info!("Round over after {:?}", game.elapsed);
+        next_state.set(GameState::GameOver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::settings::CakeMovement;
+    use crate::test_support::*;
+
+    fn play_mode(app: &mut App, mode: GameMode) {
+        app.world
+            .run_system_once(move |mut commands: Commands| start_mode(&mut commands, mode));
+    }
+
+    #[test]
+    fn time_attack_counts_down_and_cakes_add_time() {
+        let mut app = cake_app(CakeMovement::Stationary);
+        play_mode(&mut app, GameMode::TimeAttack);
+        let (i, j) = player_position(&app, 0);
+        move_cake(&mut app, i + 1.0, j);
+
+        press(&mut app, KeyCode::ArrowUp);
+        run_frames(&mut app, 60);
+
+        let game = app.world.resource::<Game>();
+        assert_eq!(game.players[0].cake_eaten, 1);
+        assert_eq!(game.time_bonus, Duration::from_secs(5));
+        let left = time_left(game, app.world.get_resource::<RoundLength>()).unwrap();
+        assert_eq!(left, Duration::from_secs(65) - game.elapsed);
+    }
+
+    #[test]
+    fn endless_rounds_have_no_clock() {
+        let mut app = test_app(1);
+        app.insert_resource(RoundLength(TICK_DURATION * 5));
+        play_mode(&mut app, GameMode::Endless);
+
+        run_frames(&mut app, 30);
+
+        assert_eq!(*app.world.resource::<State<GameState>>().get(), GameState::Playing);
+        assert_eq!(*app.world.resource::<GameMode>(), GameMode::Endless);
+        assert_eq!(
+            time_left(app.world.resource::<Game>(), app.world.get_resource::<RoundLength>()),
+            None
+        );
+    }
 }
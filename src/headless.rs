@@ -0,0 +1,185 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+use crate::game_state::*;
+use crate::pickup::PickupKinds;
+
+/// One line of an input script, the player holds `input` from `tick` until the next step
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptStep {
+    pub tick: u32,
+    pub player: usize,
+    pub input: PlayerInput,
+}
+
+/// Scripted inputs for a headless run, where every frame is one gameplay tick.
+///
+/// Each line is `<tick> <player> [up] [down] [left] [right]`, players are numbered from 1,
+/// a line without keys releases everything and `#` starts a comment.
+#[derive(Resource, Default, Debug)]
+pub struct InputScript {
+    pub steps: Vec<ScriptStep>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<InputScript, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read script {}: {error}", path.display()))?;
+        InputScript::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<InputScript, String> {
+        let mut steps = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| format!("script line {}: {message}", number + 1);
+            let mut words = line.split_whitespace();
+
+            let tick = words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .ok_or_else(|| error("expected a tick number"))?;
+            let player = words
+                .next()
+                .and_then(|word| word.parse::<usize>().ok())
+                .filter(|player| *player > 0)
+                .ok_or_else(|| error("expected a player number starting at 1"))?;
+
+            let mut input = PlayerInput::default();
+            for word in words {
+                match word {
+                    "up" => input.up = true,
+                    "down" => input.down = true,
+                    "left" => input.left = true,
+                    "right" => input.right = true,
+                    _ => return Err(error(&format!("unknown key '{word}'"))),
+                }
+            }
+
+            steps.push(ScriptStep {
+                tick,
+                player: player - 1,
+                input,
+            });
+        }
+
+        steps.sort_by_key(|step| step.tick);
+        Ok(InputScript { steps })
+    }
+}
+
+/// Counts the simulated ticks and stops the app after `ticks`
+#[derive(Resource)]
+pub struct HeadlessRun {
+    pub ticks: u32,
+    pub tick: u32,
+}
+
+impl HeadlessRun {
+    pub fn new(ticks: u32) -> Self {
+        HeadlessRun { ticks, tick: 0 }
+    }
+}
+
+pub fn apply_input_script(run: Res<HeadlessRun>, script: Res<InputScript>, mut game: ResMut<Game>) {
+    for step in script.steps.iter().filter(|step| step.tick == run.tick) {
+        if let Some(player) = game.players.get_mut(step.player) {
+            player.input = step.input;
+        } else {
+            warn!("Script tick {} refers to missing player {}", step.tick, step.player + 1);
+        }
+    }
+}
+
+pub fn finish_headless_run(
+    mut run: ResMut<HeadlessRun>,
+    game: Res<Game>,
+    kinds: Res<PickupKinds>,
+    board_seed: Res<BoardSeed>,
+    mut exit: EventWriter<AppExit>,
+) {
+    run.tick += 1;
+
+    if run.tick >= run.ticks {
+        print!("{}", game_report(&game, &kinds, run.tick, board_seed.0));
+        exit.send(AppExit);
+    }
+}
+
+/// Plain text summary of the game, printed when a headless run ends
+pub fn game_report(game: &Game, kinds: &PickupKinds, ticks: u32, board_seed: u64) -> String {
+    let mut report = String::new();
+
+    writeln!(report, "ticks: {ticks}").unwrap();
+    writeln!(report, "seed: {board_seed}").unwrap();
+
+    for (index, player) in game.players.iter().enumerate() {
+        writeln!(
+            report,
+            "player {}: position: {:.2}, {:.2} rotation: {:.2} score: {} cakes: {}",
+            index + 1,
+            player.i,
+            player.j,
+            player.rotation,
+            player.score,
+            player.cake_eaten
+        )
+        .unwrap();
+    }
+
+    // the kinds that are always on the board, or waiting to come back
+    for (index, kind) in kinds.0.iter().enumerate().filter(|(_, kind)| kind.respawn.is_some()) {
+        match game.pickups.iter().find(|pickup| pickup.kind == index) {
+            Some(pickup) => {
+                writeln!(report, "{}: {:.2}, {:.2}", kind.name, pickup.i, pickup.j).unwrap()
+            }
+            None => writeln!(report, "{}: eaten", kind.name).unwrap(),
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn input_script_holds_keys_until_the_next_step() {
+        let script = InputScript::parse("# comment\n0 1 up\n5 1\n").unwrap();
+        let mut app = test_app(1);
+
+        // the script drives the player instead of the keyboard
+        app.world.resource_mut::<Game>().players[0].controls = None;
+
+        app.insert_resource(script)
+            .insert_resource(HeadlessRun::new(u32::MAX))
+            .add_systems(
+                Update,
+                finish_headless_run,
+            )
+            .add_systems(PreUpdate, apply_input_script);
+
+        let (start_i, _) = player_position(&app, 0);
+        run_frames(&mut app, 20);
+
+        // five frames of up, then nothing
+        let (i, _) = player_position(&app, 0);
+        assert!((i - (start_i + 5.0 * MOVE_STEP)).abs() < 0.001, "i = {i}");
+    }
+
+    #[test]
+    fn input_script_rejects_unknown_keys() {
+        assert!(InputScript::parse("0 1 jump").is_err());
+        assert!(InputScript::parse("0 0 up").is_err());
+    }
+}
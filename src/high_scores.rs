@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How many scores the table keeps
+pub const HIGH_SCORE_LIMIT: usize = 10;
+
+const MAX_NAME_LENGTH: usize = 12;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HighScore {
+    pub name: String,
+    pub score: i32,
+    pub cakes: u32,
+}
+
+/// The best scores on this machine, highest first
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Reads the table, a missing file is an empty table and a corrupted one is kept next to
+    /// it as `.corrupt` so the next save doesn't silently throw it away
+    pub fn load(path: &Path) -> HighScores {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                info!("No high scores yet at {}", path.display());
+                return HighScores::default();
+            }
+            Err(error) => {
+                warn!("Failed to read high scores {}: {error}", path.display());
+                return HighScores::default();
+            }
+        };
+
+        match ron::from_str::<HighScores>(&text) {
+            Ok(mut high_scores) => {
+                high_scores.sort();
+                high_scores
+            }
+            Err(error) => {
+                let backup = path.with_extension("ron.corrupt");
+                warn!(
+                    "High scores {} are corrupted ({error}), moving them to {}",
+                    path.display(),
+                    backup.display()
+                );
+                std::fs::rename(path, &backup).ok();
+                HighScores::default()
+            }
+        }
+    }
+
+    // written to a temporary file first so a crash can't leave half a table behind
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|error| format!("failed to create {}: {error}", dir.display()))?;
+        }
+
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        let temporary = path.with_extension("ron.tmp");
+        std::fs::write(&temporary, text)
+            .and_then(|()| std::fs::rename(&temporary, path))
+            .map_err(|error| format!("failed to write high scores {}: {error}", path.display()))
+    }
+
+    pub fn qualifies(&self, score: i32) -> bool {
+        self.entries.len() < HIGH_SCORE_LIMIT
+            || self.entries.iter().any(|entry| score > entry.score)
+    }
+
+    /// Adds a score and returns its place in the table, `None` if it didn't make it
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        // later entries go below earlier ones with the same score
+        let place = self
+            .entries
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.entries.len());
+
+        if place >= HIGH_SCORE_LIMIT {
+            return None;
+        }
+
+        self.entries.insert(place, entry);
+        self.entries.truncate(HIGH_SCORE_LIMIT);
+        Some(place)
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by_key(|entry| std::cmp::Reverse(entry.score));
+        self.entries.truncate(HIGH_SCORE_LIMIT);
+    }
+}
+
+/// Where the high scores are kept
+#[derive(Resource)]
+pub struct HighScoreFile(pub PathBuf);
+
+impl Default for HighScoreFile {
+    // the user's data directory, e.g. ~/.local/share/alien_explorer on Linux
+    fn default() -> Self {
+        let dir = dirs::data_dir()
+            .map(|dir| dir.join("alien_explorer"))
+            .unwrap_or_default();
+        HighScoreFile(dir.join("high_scores.ron"))
+    }
+}
+
+/// Name being typed on the game over screen, for each player whose score made the table
+#[derive(Resource, Default)]
+pub struct NameEntry {
+    /// Players still to enter a name, the first one is typing
+    pub players: Vec<usize>,
+    pub name: String,
+}
+
+impl NameEntry {
+    pub fn type_char(&mut self, c: char) {
+        if !c.is_control() && self.name.chars().count() < MAX_NAME_LENGTH {
+            self.name.push(c);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        self.name.pop();
+    }
+}
+
+/// The table as shown on screen
+pub fn high_score_table(high_scores: &HighScores) -> String {
+    if high_scores.entries.is_empty() {
+        return "No high scores yet".to_string();
+    }
+
+    high_scores
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(place, entry)| {
+            format!(
+                "{:>2}. {:<12} {:>5}  cakes: {}",
+                place + 1,
+                entry.name,
+                entry.score,
+                entry.cakes
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    fn high_score(name: &str, score: i32) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            cakes: 0,
+        }
+    }
+
+    #[test]
+    fn high_scores_keep_the_best_in_order() {
+        let mut high_scores = HighScores::default();
+
+        for score in 0..HIGH_SCORE_LIMIT as i32 {
+            high_scores.insert(high_score("low", score));
+        }
+        assert!(!high_scores.qualifies(0));
+        assert!(high_scores.qualifies(5));
+
+        assert_eq!(high_scores.insert(high_score("best", 100)), Some(0));
+        // 100, 9, 8, 7, 6, 5, then the tie
+        assert_eq!(high_scores.insert(high_score("tied", 5)), Some(6));
+        assert_eq!(high_scores.insert(high_score("worst", -1)), None);
+
+        assert_eq!(high_scores.entries.len(), HIGH_SCORE_LIMIT);
+        assert_eq!(high_scores.entries[0].name, "best");
+        assert_eq!(high_scores.entries[5].name, "low");
+        assert_eq!(high_scores.entries[6].name, "tied");
+        assert!(high_scores
+            .entries
+            .windows(2)
+            .all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn high_scores_survive_missing_and_corrupted_files() {
+        let dir = std::env::temp_dir().join("alien_explorer_test_high_scores");
+        std::fs::remove_dir_all(&dir).ok();
+        let path = dir.join("high_scores.ron");
+
+        assert_eq!(HighScores::load(&path), HighScores::default());
+
+        let mut high_scores = HighScores::default();
+        high_scores.insert(high_score("alien", 12));
+        high_scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path), high_scores);
+
+        std::fs::write(&path, "(entries: [(name: \"alien\", sco").unwrap();
+        assert_eq!(HighScores::load(&path), HighScores::default());
+        assert!(!path.exists());
+        assert!(path.with_extension("ron.corrupt").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+
+/// Where the fixed-timestep gameplay put an entity on the last two ticks. The rendered
+/// `Transform` is blended between them so movement stays smooth at any frame rate.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SimulatedTransform {
+    pub previous: Transform,
+    pub current: Transform,
+}
+
+impl SimulatedTransform {
+    pub fn new(transform: Transform) -> Self {
+        SimulatedTransform {
+            previous: transform,
+            current: transform,
+        }
+    }
+}
+
+// the result of the last tick becomes the start of the blend for the next one
+pub fn advance_simulated_transforms(mut query: Query<&mut SimulatedTransform>) {
+    for mut simulated in query.iter_mut() {
+        simulated.previous = simulated.current;
+    }
+}
+
+pub fn interpolate_transforms(
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&SimulatedTransform, &mut Transform)>,
+) {
+    // how far we are between the last tick and the next one
+    let t = fixed_time.overstep_fraction();
+
+    for (simulated, mut transform) in query.iter_mut() {
+        transform.translation = simulated
+            .previous
+            .translation
+            .lerp(simulated.current.translation, t);
+        transform.rotation = simulated.previous.rotation.slerp(simulated.current.rotation, t);
+    }
+}
@@ -0,0 +1,290 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Cell};
+use crate::game_state::*;
+
+/// Something to do before a level is complete
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Objective {
+    /// Eat this many cakes between the aliens
+    EatCakes(u32),
+    /// Keep playing for this many seconds
+    Survive(f32),
+    /// Get an alien onto this tile, moved onto the board if it is off a small one
+    ReachExit(Cell),
+}
+
+/// One level, read from `level_<number>.ron` in the level directory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Level {
+    pub name: String,
+    pub objectives: Vec<Objective>,
+}
+
+impl Level {
+    pub fn load(path: &Path) -> Result<Level, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read level {}: {error}", path.display()))?;
+        Level::parse(&text).map_err(|error| format!("invalid level {}: {error}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Level, String> {
+        let level: Level = ron::from_str(text).map_err(|error| error.to_string())?;
+        for objective in &level.objectives {
+            if let Objective::Survive(seconds) = objective {
+                if !(seconds.is_finite() && *seconds >= 0.0) {
+                    return Err(format!("cannot survive for {seconds} seconds"));
+                }
+            }
+        }
+        Ok(level)
+    }
+
+    /// The exit tile, if the level has one
+    pub fn exit(&self, board: &Board) -> Option<Cell> {
+        self.objectives.iter().find_map(|objective| match objective {
+            Objective::ReachExit(cell) => {
+                let position = board.clamp(Vec3::new(cell.i as f32, 0.0, cell.j as f32));
+                board.world_to_cell(position)
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Where the level files are kept
+#[derive(Resource, Clone)]
+pub struct LevelDirectory(pub PathBuf);
+
+impl Default for LevelDirectory {
+    fn default() -> Self {
+        LevelDirectory(PathBuf::from("assets/levels"))
+    }
+}
+
+impl LevelDirectory {
+    pub fn level_path(&self, number: usize) -> PathBuf {
+        self.0.join(format!("level_{number}.ron"))
+    }
+
+    /// Level `number`, `None` when there is no such file
+    pub fn load(&self, number: usize) -> Option<CurrentLevel> {
+        let path = self.level_path(number);
+        if !path.exists() {
+            return None;
+        }
+
+        match Level::load(&path) {
+            Ok(level) => Some(CurrentLevel {
+                number,
+                level,
+                cakes_at_start: 0,
+            }),
+            Err(error) => {
+                warn!("{error}");
+                None
+            }
+        }
+    }
+}
+
+/// The level being played, levels are only played in the windowed game
+#[derive(Resource, Clone, Debug)]
+pub struct CurrentLevel {
+    pub number: usize,
+    pub level: Level,
+    /// Cakes already eaten when the level started
+    pub cakes_at_start: u32,
+}
+
+impl CurrentLevel {
+    pub fn cakes_eaten(&self, game: &Game) -> u32 {
+        let cakes: u32 = game.players.iter().map(|player| player.cake_eaten).sum();
+        cakes.saturating_sub(self.cakes_at_start)
+    }
+
+    pub fn is_met(&self, objective: Objective, game: &Game) -> bool {
+        match objective {
+            Objective::EatCakes(count) => self.cakes_eaten(game) >= count,
+            Objective::Survive(seconds) => game.elapsed >= Duration::from_secs_f32(seconds),
+            Objective::ReachExit(_) => {
+                let Some(exit) = self.level.exit(&game.board) else {
+                    return false;
+                };
+                game.players.iter().any(|player| {
+                    game.board.world_to_cell(Vec3::new(player.i, 0.0, player.j)) == Some(exit)
+                })
+            }
+        }
+    }
+
+    pub fn is_complete(&self, game: &Game) -> bool {
+        self.level
+            .objectives
+            .iter()
+            .all(|objective| self.is_met(*objective, game))
+    }
+
+    /// The level and how far along each objective is, for the scoreboard
+    pub fn progress_text(&self, game: &Game) -> String {
+        let mut text = format!("level {}: {}", self.number, self.level.name);
+
+        for objective in &self.level.objectives {
+            let done = if self.is_met(*objective, game) { " (done)" } else { "" };
+            text += &match objective {
+                Objective::EatCakes(count) => {
+                    format!("\ncakes: {}/{count}{done}", self.cakes_eaten(game).min(*count))
+                }
+                Objective::Survive(seconds) => format!(
+                    "\nsurvive: {:.0}/{seconds:.0}s{done}",
+                    game.elapsed.as_secs_f32().min(*seconds)
+                ),
+                Objective::ReachExit(_) => format!("\nreach the exit{done}"),
+            };
+        }
+
+        text
+    }
+}
+
+/// Tags the marker over the exit tile
+#[derive(Component)]
+pub struct ExitMarker;
+
+// runs once per gameplay tick, a finished level waits on the level complete screen
+pub fn check_objectives(
+    game: Res<Game>,
+    level: Res<CurrentLevel>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if level.is_complete(&game) {
+        info!("Level {} complete after {:?}", level.number, game.elapsed);
+        next_state.set(GameState::LevelComplete);
+    }
+}
+
+/// Begin `level` with a fresh clock, the scores carry over
+pub fn start_level(game: &mut Game, level: &mut CurrentLevel) {
+    info!("Starting level {}: {}", level.number, level.level.name);
+
+    level.cakes_at_start = game.players.iter().map(|player| player.cake_eaten).sum();
+    game.elapsed = default();
+    game.time_bonus = default();
+}
+
+// put a light over the exit of each new level
+pub fn update_exit_marker(
+    mut commands: Commands,
+    game: Res<Game>,
+    level: Res<CurrentLevel>,
+    markers: Query<Entity, With<ExitMarker>>,
+) {
+    for entity in markers.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let Some(exit) = level.level.exit(&game.board) else {
+        return;
+    };
+
+    commands.spawn((
+        PointLightBundle {
+            point_light: PointLight {
+                color: Color::rgb(0.0, 1.0, 0.4),
+                intensity: 1500.0,
+                range: 5.0,
+                ..default()
+            },
+            transform: Transform::from_translation(
+                game.board.cell_to_world(exit) + Vec3::Y * 1.5,
+            ),
+            ..default()
+        },
+        ExitMarker,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pickup::collect_pickups;
+    use crate::settings::CakeMovement;
+    use crate::test_support::*;
+
+    #[test]
+    fn levels_are_read_in_order() {
+        let directory = LevelDirectory::default();
+        let numbers: Vec<usize> = (1..=4)
+            .filter_map(|number| directory.load(number))
+            .map(|level| level.number)
+            .collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert_eq!(directory.load(1).unwrap().level.objectives, [Objective::EatCakes(3)]);
+    }
+
+    #[test]
+    fn levels_with_impossible_objectives_are_skipped() {
+        let dir = std::env::temp_dir().join("alien_explorer_test_levels");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let directory = LevelDirectory(dir);
+
+        for seconds in ["-5.0", "NaN", "inf"] {
+            let text = format!("(name: \"bad\", objectives: [Survive({seconds})])");
+            assert!(Level::parse(&text).is_err(), "{seconds}");
+
+            std::fs::write(directory.level_path(1), text).unwrap();
+            assert!(directory.load(1).is_none());
+        }
+
+        assert!(Level::parse("(name: \"ok\", objectives: [Survive(30.0)])").is_ok());
+    }
+
+    #[test]
+    fn meeting_every_objective_completes_the_level() {
+        let mut app = cake_app(CakeMovement::Stationary);
+        let (i, j) = player_position(&app, 0);
+        move_cake(&mut app, i + 1.0, j);
+
+        let level = CurrentLevel {
+            number: 1,
+            level: Level {
+                name: "test".to_string(),
+                objectives: vec![
+                    Objective::EatCakes(1),
+                    Objective::ReachExit(Cell::new(i as usize + 3, j as usize)),
+                    Objective::Survive(0.2),
+                ],
+            },
+            cakes_at_start: 0,
+        };
+        app.insert_resource(level)
+            .add_systems(FixedUpdate, check_objectives.after(collect_pickups));
+
+        press(&mut app, KeyCode::ArrowUp);
+        run_frames(&mut app, 15);
+        {
+            let game = app.world.resource::<Game>();
+            let progress = app.world.resource::<CurrentLevel>().progress_text(game);
+            assert_eq!(
+                progress,
+                "level 1: test\ncakes: 1/1 (done)\nreach the exit\nsurvive: 0/0s (done)"
+            );
+            assert_eq!(app.world.resource::<State<GameState>>().get(), &GameState::Playing);
+        }
+
+        run_frames(&mut app, 20);
+        assert_eq!(app.world.resource::<State<GameState>>().get(), &GameState::LevelComplete);
+
+        // the next level counts its own cakes
+        let mut game = std::mem::take(&mut *app.world.resource_mut::<Game>());
+        let mut next = LevelDirectory::default().load(2).unwrap();
+        start_level(&mut game, &mut next);
+        assert_eq!(next.cakes_eaten(&game), 0);
+        assert_eq!(game.elapsed, Duration::ZERO);
+    }
+}
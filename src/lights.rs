@@ -1 +1,572 @@
+use std::f32::consts::TAU;
 
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::*;
+use crate::settings::Settings;
+
+/// Strength of the lights carried by the aliens and the cake, full at night
+pub const NIGHT_LIGHT_INTENSITY: f32 = 1000.0;
+
+// the cycle starts in the morning, as a fraction of a day from sunrise
+const MORNING: f32 = 0.1;
+
+// degrees around the vertical the sun rises from
+const SUN_YAW: f32 = 30.0;
+
+/// Colour and strength of the light that reaches everything
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ambient {
+    pub color: (f32, f32, f32),
+    pub brightness: f32,
+}
+
+/// The directional light over the board
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SunLight {
+    pub color: (f32, f32, f32),
+    pub illuminance: f32,
+    /// Degrees above (positive) or below (negative) the horizon the light points
+    pub pitch: f32,
+    /// Degrees around the vertical
+    pub yaw: f32,
+}
+
+/// The point light high above the board, the one that casts shadows
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LampLight {
+    pub color: (f32, f32, f32),
+    pub intensity: f32,
+    pub range: f32,
+    pub position: (f32, f32, f32),
+}
+
+/// A spot light on every alien, shining where it walks
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HeadlampLight {
+    pub color: (f32, f32, f32),
+    pub intensity: f32,
+    pub range: f32,
+    /// Half the width of the beam, in radians
+    pub angle: f32,
+}
+
+/// One lighting mood, as described in `assets/lighting.ron`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct LightingPreset {
+    pub name: String,
+    /// How high the sun is when the day/night cycle shows this preset, -1 to 1
+    #[serde(default)]
+    pub elevation: Option<f32>,
+    /// Background colour behind the board
+    pub sky: (f32, f32, f32),
+    pub ambient: Ambient,
+    #[serde(default)]
+    pub sun: Option<SunLight>,
+    pub lamp: LampLight,
+    #[serde(default)]
+    pub headlamp: Option<HeadlampLight>,
+}
+
+/// Every lighting preset, read from the data built into the game
+#[derive(Resource, Clone, Debug)]
+pub struct LightingPresets(pub Vec<LightingPreset>);
+
+impl LightingPresets {
+    pub fn parse(text: &str) -> Result<LightingPresets, String> {
+        let presets: Vec<LightingPreset> = ron::from_str(text).map_err(|error| error.to_string())?;
+        if presets.is_empty() {
+            return Err("no lighting presets".to_string());
+        }
+        Ok(LightingPresets(presets))
+    }
+
+    /// The preset called `name`
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.0.iter().position(|preset| preset.name == name)
+    }
+
+    /// The lighting with the sun at `elevation`, from -1 at midnight to 1 at noon, blended
+    /// between the presets with the nearest elevations. `None` when no preset has one.
+    pub fn at_elevation(&self, elevation: f32) -> Option<BlendedLighting> {
+        let mut cycle: Vec<(f32, usize)> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter_map(|(index, preset)| Some((preset.elevation?, index)))
+            .collect();
+        cycle.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let (below, above) = match cycle.iter().position(|(height, _)| *height >= elevation) {
+            None => (*cycle.last()?, *cycle.last()?),
+            Some(0) => (cycle[0], cycle[0]),
+            Some(index) => (cycle[index - 1], cycle[index]),
+        };
+        let t = if above.0 > below.0 {
+            (elevation - below.0) / (above.0 - below.0)
+        } else {
+            0.0
+        };
+
+        let (low, high) = (&self.0[below.1], &self.0[above.1]);
+        let mix = |a: (f32, f32, f32), b: (f32, f32, f32)| {
+            color((
+                a.0 + (b.0 - a.0) * t,
+                a.1 + (b.1 - a.1) * t,
+                a.2 + (b.2 - a.2) * t,
+            ))
+        };
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        // a preset without a sun fades the other one's out
+        let (low_sun, high_sun) = match (&low.sun, &high.sun) {
+            (Some(low_sun), Some(high_sun)) => {
+                ((low_sun.color, low_sun.illuminance), (high_sun.color, high_sun.illuminance))
+            }
+            (Some(sun), None) => ((sun.color, sun.illuminance), (sun.color, 0.0)),
+            (None, Some(sun)) => ((sun.color, 0.0), (sun.color, sun.illuminance)),
+            (None, None) => (((1.0, 1.0, 1.0), 0.0), ((1.0, 1.0, 1.0), 0.0)),
+        };
+
+        Some(BlendedLighting {
+            sky: mix(low.sky, high.sky),
+            ambient_color: mix(low.ambient.color, high.ambient.color),
+            ambient_brightness: lerp(low.ambient.brightness, high.ambient.brightness),
+            sun_color: mix(low_sun.0, high_sun.0),
+            sun_illuminance: lerp(low_sun.1, high_sun.1),
+            lamp_color: mix(low.lamp.color, high.lamp.color),
+            lamp_intensity: lerp(low.lamp.intensity, high.lamp.intensity),
+            nearest: if t < 0.5 { below.1 } else { above.1 },
+        })
+    }
+}
+
+/// Lighting part way between two presets
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlendedLighting {
+    pub sky: Color,
+    pub ambient_color: Color,
+    pub ambient_brightness: f32,
+    pub sun_color: Color,
+    pub sun_illuminance: f32,
+    pub lamp_color: Color,
+    pub lamp_intensity: f32,
+    /// Index of the preset it is closest to
+    pub nearest: usize,
+}
+
+impl Default for LightingPresets {
+    fn default() -> Self {
+        LightingPresets::parse(include_str!("../assets/lighting.ron"))
+            .unwrap_or_else(|error| panic!("invalid assets/lighting.ron: {error}"))
+    }
+}
+
+/// The lighting preset in use, an index into `LightingPresets`
+#[derive(Resource, Default, PartialEq)]
+pub struct Lighting(pub usize);
+
+/// Time of the day/night cycle, as a fraction of a day from sunrise
+#[derive(Resource)]
+pub struct DayNight {
+    pub time: f32,
+    /// Whether the cycle had the lights last frame
+    pub running: bool,
+}
+
+impl Default for DayNight {
+    fn default() -> Self {
+        DayNight {
+            time: MORNING,
+            running: false,
+        }
+    }
+}
+
+/// The first time of day after `time` when the sun is at `elevation`, as it rises or as it sets
+pub fn time_at_elevation(time: f32, elevation: f32) -> f32 {
+    let rising = elevation.clamp(-1.0, 1.0).asin() / TAU;
+    // how far ahead a time of day is, a whole day for the time it is now
+    let ahead = |at: f32| {
+        let ahead = (at - time).rem_euclid(1.0);
+        if ahead < 1e-4 {
+            ahead + 1.0
+        } else {
+            ahead
+        }
+    };
+
+    (time + ahead(rising).min(ahead(0.5 - rising))).rem_euclid(1.0)
+}
+
+/// Tags the light that casts shadows, switched off by the shadow quality setting
+#[derive(Component)]
+pub struct ShadowLight;
+
+/// Tags the point light over the board
+#[derive(Component)]
+pub struct Lamp;
+
+/// Tags a headlamp, a child of an alien
+#[derive(Component)]
+pub struct Headlamp;
+
+/// Tags the lights carried by the aliens and the cake, dimmed while the sun is up
+#[derive(Component)]
+pub struct NightLight;
+
+/// Tags an alien wearing a headlamp
+#[derive(Component)]
+pub struct WearsHeadlamp;
+
+fn color((red, green, blue): (f32, f32, f32)) -> Color {
+    Color::rgb(red, green, blue)
+}
+
+pub fn spawn_lights(mut commands: Commands) {
+    info!("Spawning lamp and sun");
+
+    commands.spawn((PointLightBundle::default(), Lamp, ShadowLight));
+
+    // update_shadow_cascades fits its shadows to the board
+    commands.spawn(DirectionalLightBundle::default());
+}
+
+// L goes on to the next preset. While the day/night cycle runs the sun picks the preset, so
+// the cycle skips ahead to the time of day with the sun where the next preset has it.
+pub fn switch_lighting(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    presets: Res<LightingPresets>,
+    mut lighting: ResMut<Lighting>,
+    mut day_night: ResMut<DayNight>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::KeyL) {
+        return;
+    }
+
+    let count = presets.0.len();
+    if settings.graphics.day_length == 0 {
+        lighting.0 = (lighting.0 + 1) % count;
+        return;
+    }
+
+    // presets without an elevation never come up in the cycle
+    let next = (1..=count)
+        .map(|step| (lighting.0 + step) % count)
+        .find_map(|index| presets.0[index].elevation);
+    if let Some(elevation) = next {
+        day_night.time = time_at_elevation(day_night.time, elevation);
+    }
+}
+
+// set the lights to the chosen preset, the shadows are left to the shadow quality setting
+pub fn apply_lighting(
+    presets: Res<LightingPresets>,
+    lighting: Res<Lighting>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    mut lamps: Query<(&mut PointLight, &mut Transform), With<Lamp>>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform, &mut Visibility), Without<Lamp>>,
+) {
+    let Some(preset) = presets.0.get(lighting.0) else {
+        return;
+    };
+    info!("Lighting: {}", preset.name);
+
+    clear_color.0 = color(preset.sky);
+    ambient.color = color(preset.ambient.color);
+    ambient.brightness = preset.ambient.brightness;
+
+    for (mut light, mut transform) in lamps.iter_mut() {
+        light.color = color(preset.lamp.color);
+        light.intensity = preset.lamp.intensity;
+        light.range = preset.lamp.range;
+        let (x, y, z) = preset.lamp.position;
+        transform.translation = Vec3::new(x, y, z);
+    }
+
+    for (mut light, mut transform, mut visibility) in suns.iter_mut() {
+        let Some(sun) = &preset.sun else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Visible;
+        light.color = color(sun.color);
+        light.illuminance = sun.illuminance;
+        transform.rotation = Quat::from_euler(
+            EulerRot::YXZ,
+            sun.yaw.to_radians(),
+            sun.pitch.to_radians(),
+            0.0,
+        );
+    }
+}
+
+// give every alien a headlamp while the preset has them, including aliens spawned later
+pub fn update_headlamps(
+    mut commands: Commands,
+    game: Res<Game>,
+    presets: Res<LightingPresets>,
+    lighting: Res<Lighting>,
+    wearing: Query<(), With<WearsHeadlamp>>,
+    headlamps: Query<Entity, With<Headlamp>>,
+) {
+    let headlamp = presets.0.get(lighting.0).and_then(|preset| preset.headlamp.as_ref());
+
+    // a new preset takes the old headlamps off
+    if lighting.is_changed() {
+        for entity in headlamps.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let Some(headlamp) = headlamp else {
+        return;
+    };
+
+    for entity in game.players.iter().filter_map(|player| player.entity) {
+        if wearing.contains(entity) && !lighting.is_changed() {
+            continue;
+        }
+
+        commands
+            .entity(entity)
+            .insert(WearsHeadlamp)
+            .with_children(|children| {
+                children.spawn((
+                    SpotLightBundle {
+                        spot_light: SpotLight {
+                            color: color(headlamp.color),
+                            intensity: headlamp.intensity,
+                            range: headlamp.range,
+                            outer_angle: headlamp.angle,
+                            inner_angle: headlamp.angle * 0.8,
+                            ..default()
+                        },
+                        // forward and down from the head
+                        transform: Transform::from_xyz(0.0, 1.2, 0.0)
+                            .looking_at(Vec3::new(0.0, 0.0, -4.0), Vec3::Y),
+                        ..default()
+                    },
+                    Headlamp,
+                ));
+            });
+    }
+}
+
+// move the sun over the board and blend the presets by how high it is, while the cycle is on
+// in the settings. Turning it off goes back to the chosen preset.
+#[allow(clippy::too_many_arguments)]
+pub fn update_day_night(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    presets: Res<LightingPresets>,
+    mut day_night: ResMut<DayNight>,
+    mut lighting: ResMut<Lighting>,
+    mut ambient: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+    mut point_lights: Query<(&mut PointLight, Has<Lamp>, Has<NightLight>)>,
+    mut suns: Query<(&mut DirectionalLight, &mut Transform, &mut Visibility)>,
+) {
+    let day_length = settings.graphics.day_length;
+    if day_length == 0 {
+        if day_night.running {
+            info!("Day/night cycle off");
+            day_night.running = false;
+            lighting.set_changed();
+            for (mut light, _, night_light) in point_lights.iter_mut() {
+                if night_light {
+                    light.intensity = NIGHT_LIGHT_INTENSITY;
+                }
+            }
+        }
+        return;
+    }
+
+    day_night.running = true;
+    day_night.time = (day_night.time + time.delta_seconds() / day_length as f32).fract();
+    let angle = day_night.time * TAU;
+    let elevation = angle.sin();
+    let Some(blended) = presets.at_elevation(elevation) else {
+        return;
+    };
+
+    // the headlamps come with the night preset
+    lighting.set_if_neq(Lighting(blended.nearest));
+
+    clear_color.0 = blended.sky;
+    ambient.color = blended.ambient_color;
+    ambient.brightness = blended.ambient_brightness;
+
+    // the carried lights come on as the sun sets
+    let darkness = ((0.2 - elevation) / 0.4).clamp(0.0, 1.0);
+    for (mut light, lamp, night_light) in point_lights.iter_mut() {
+        if lamp {
+            light.color = blended.lamp_color;
+            light.intensity = blended.lamp_intensity;
+        } else if night_light {
+            light.intensity = NIGHT_LIGHT_INTENSITY * darkness;
+        }
+    }
+
+    // rises along -Z, overhead at noon and under the board at night
+    for (mut light, mut transform, mut visibility) in suns.iter_mut() {
+        light.color = blended.sun_color;
+        light.illuminance = blended.sun_illuminance;
+        transform.rotation =
+            Quat::from_rotation_y(SUN_YAW.to_radians()) * Quat::from_rotation_x(-angle);
+        *visibility = if elevation > 0.0 {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn lighting_presets_switch_at_runtime() {
+        let presets = LightingPresets::default();
+        let names: Vec<&str> = presets.0.iter().map(|preset| preset.name.as_str()).collect();
+        assert_eq!(names, ["alien day", "dusk", "night"]);
+        assert!(LightingPresets::parse("[]").is_err());
+
+        let mut app = test_app(1);
+        app.insert_resource(presets)
+            .init_resource::<Lighting>()
+            .init_resource::<DayNight>()
+            .init_resource::<AmbientLight>()
+            .init_resource::<ClearColor>()
+            .add_systems(
+                Update,
+                (
+                    switch_lighting,
+                    apply_lighting
+                        .after(switch_lighting)
+                        .run_if(resource_changed::<Lighting>),
+                    update_headlamps.after(switch_lighting),
+                ),
+            );
+        app.world.run_system_once(spawn_lights);
+        app.update();
+        let mut headlamps = app.world.query_filtered::<&Parent, With<Headlamp>>();
+        assert_eq!(app.world.resource::<AmbientLight>().brightness, 80.0);
+        assert_eq!(headlamps.iter(&app.world).count(), 0);
+
+        // there is no input plugin to clear the key after a frame
+        let tap_l = |app: &mut App| {
+            press(app, KeyCode::KeyL);
+            app.update();
+            app.world.resource_mut::<ButtonInput<KeyCode>>().clear();
+            release_all(app);
+        };
+
+        // L twice, on to the night with the alien wearing a headlamp
+        tap_l(&mut app);
+        tap_l(&mut app);
+        assert_eq!(app.world.resource::<Lighting>().0, 2);
+        assert_eq!(app.world.resource::<AmbientLight>().brightness, 5.0);
+        let player = app.world.resource::<Game>().players[0].entity.unwrap();
+        let parents: Vec<Entity> = headlamps.iter(&app.world).map(|parent| parent.get()).collect();
+        assert_eq!(parents, [player]);
+        let sun = app.world.query_filtered::<Entity, With<DirectionalLight>>().single(&app.world);
+        assert_eq!(app.world.get::<Visibility>(sun), Some(&Visibility::Hidden));
+
+        // and back round to the day, the headlamp comes off
+        tap_l(&mut app);
+        assert_eq!(headlamps.iter(&app.world).count(), 0);
+        assert_eq!(app.world.get::<Visibility>(sun), Some(&Visibility::Visible));
+    }
+
+    #[test]
+    fn lighting_key_skips_through_the_day_night_cycle() {
+        // from the morning on to sunset, then after it the sun sinks to the night's elevation
+        assert!((time_at_elevation(0.1, 0.0) - 0.5).abs() < 1e-5);
+        let night = time_at_elevation(0.5, -0.4);
+        assert!(night > 0.5 && night < 0.75, "night at {night}");
+        assert!(((night * TAU).sin() + 0.4).abs() < 1e-5);
+        // and round to the next morning
+        assert!(time_at_elevation(night, 0.6) < 0.25);
+
+        let mut app = test_app(1);
+        app.init_resource::<LightingPresets>()
+            .init_resource::<Lighting>()
+            .init_resource::<DayNight>()
+            .init_resource::<AmbientLight>()
+            .init_resource::<ClearColor>()
+            .add_systems(Update, (switch_lighting, update_day_night.after(switch_lighting)));
+        app.world.resource_mut::<Settings>().graphics.day_length = 60;
+        app.update();
+        assert_eq!(app.world.resource::<Lighting>().0, 0);
+
+        // each tap moves the sun on to the next preset instead of being undone by the cycle
+        for expected in [1, 2, 0] {
+            press(&mut app, KeyCode::KeyL);
+            app.update();
+            app.world.resource_mut::<ButtonInput<KeyCode>>().clear();
+            release_all(&mut app);
+            app.update();
+            assert_eq!(app.world.resource::<Lighting>().0, expected);
+        }
+    }
+
+    #[test]
+    fn day_night_cycle_blends_the_presets() {
+        let presets = LightingPresets::default();
+        let day = presets.at_elevation(1.0).unwrap();
+        assert_eq!((day.nearest, day.ambient_brightness), (0, 80.0));
+        let evening = presets.at_elevation(0.3).unwrap();
+        assert_eq!(evening.ambient_brightness, 55.0);
+        let night = presets.at_elevation(-1.0).unwrap();
+        assert_eq!((night.nearest, night.sun_illuminance), (2, 0.0));
+
+        let mut app = test_app(1);
+        app.insert_resource(presets)
+            .init_resource::<Lighting>()
+            .init_resource::<AmbientLight>()
+            .init_resource::<ClearColor>()
+            .insert_resource(DayNight {
+                time: 0.75,
+                running: false,
+            })
+            .add_systems(Update, (update_day_night, update_headlamps.after(update_day_night)));
+        app.world.resource_mut::<Settings>().graphics.day_length = 60;
+        app.world.run_system_once(spawn_lights);
+        let night_lights = |app: &mut App| -> Vec<f32> {
+            app.world
+                .query_filtered::<&PointLight, With<NightLight>>()
+                .iter(&app.world)
+                .map(|light| light.intensity)
+                .collect()
+        };
+
+        // midnight, the alien and the cake light up and the headlamp goes on
+        app.update();
+        assert_eq!(app.world.resource::<Lighting>().0, 2);
+        assert_eq!(app.world.resource::<AmbientLight>().brightness, 5.0);
+        assert_eq!(night_lights(&mut app), [NIGHT_LIGHT_INTENSITY; 2]);
+        app.update();
+        let headlamps = app.world.query::<&Headlamp>().iter(&app.world).count();
+        assert_eq!(headlamps, 1);
+
+        // noon
+        app.world.resource_mut::<DayNight>().time = 0.25;
+        app.update();
+        assert_eq!(app.world.resource::<Lighting>().0, 0);
+        assert_eq!(night_lights(&mut app), [0.0; 2]);
+
+        // off again, back to the lights always on
+        app.world.resource_mut::<Settings>().graphics.day_length = 0;
+        app.update();
+        assert!(!app.world.resource::<DayNight>().running);
+        assert_eq!(night_lights(&mut app), [NIGHT_LIGHT_INTENSITY; 2]);
+    }
+}
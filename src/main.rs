@@ -1,66 +1,366 @@
 #![allow(unused_imports)]
 
+use crate::audio::*;
+use crate::board::*;
 use crate::cake::*;
 use crate::camera_pan_and_orbit::*;
 use crate::camera_tracking::*;
 use crate::display::*;
+use crate::enemy::*;
 use crate::game_state::*;
+use crate::headless::*;
+use crate::high_scores::*;
+use crate::interpolation::*;
+use crate::level::*;
+use crate::lights::*;
+use crate::menu::*;
 // use crate::lines::*;
+use crate::network::*;
+use crate::particles::*;
+use crate::pickup::*;
+use crate::picking::*;
 use crate::player::*;
+use crate::replay::*;
+use crate::save::*;
+use crate::scoring::*;
+use crate::settings::*;
 use crate::spawn::*;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::input::InputSystem;
 use bevy::prelude::*;
+use bevy::time::{TimeSystem, TimeUpdateStrategy};
 use crate::cylinder::spawn_cylinders;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+mod audio;
+mod board;
 mod cake;
 mod camera_pan_and_orbit;
 mod camera_tracking;
 mod cylinder;
 mod display;
+mod enemy;
 mod game_state;
+mod headless;
+mod high_scores;
+mod interpolation;
+mod level;
 mod lights;
+mod menu;
 // mod lines;
+mod network;
+mod particles;
+mod pathfinding;
+mod pickup;
+mod picking;
 mod player;
+mod replay;
+mod save;
+mod scoring;
+mod settings;
 mod spawn;
+#[cfg(test)]
+mod test_support;
+#[cfg(test)]
+mod tests;
 
 fn main() {
-    App::new()
-        // .add_plugins((DefaultPlugins, MaterialPlugin::<LineMaterial>::default()))
-        .add_plugins(DefaultPlugins)
-        .init_resource::<Game>()
-        .insert_resource(CakeSpawnTimer(Timer::from_seconds(
-            5.0,
-            TimerMode::Repeating,
-        )))
+    let args: Vec<String> = std::env::args().collect();
+
+    let net_mode = NetMode::from_args(&args).unwrap_or_else(|error| fail(error));
+
+    let saved = arg_value(&args, "--load")
+        .map(|path| SavedGame::load(Path::new(path)).unwrap_or_else(|error| fail(error)));
+    if saved.is_some() && !matches!(net_mode, NetMode::Local) {
+        fail("--load can only be used in a local game");
+    }
+
+    let player_count = if args.iter().any(|arg| arg == "--two-player") {
+        2
+    } else {
+        1
+    };
+
+    let board_seed = match arg_value(&args, "--seed") {
+        Some(seed) => seed
+            .parse()
+            .unwrap_or_else(|_| fail(format!("invalid --seed '{seed}'"))),
+        None => rand::random(),
+    };
+
+    let settings_file = match arg_value(&args, "--settings") {
+        Some(path) => SettingsFile(PathBuf::from(path)),
+        None => SettingsFile::default(),
+    };
+    let mut settings = Settings::load(&settings_file.0);
+
+    // a save brings its own board and players
+    let (player_count, board_seed, board_size) = match &saved {
+        Some(saved) => (saved.players.len(), saved.board_seed, saved.board_size()),
+        None => (player_count, board_seed, settings.board),
+    };
+
+    let mut app = App::new();
+    app.insert_resource(settings_file);
+
+    // the lighting to start with, L switches while playing
+    if let Some(name) = arg_value(&args, "--lighting") {
+        let preset = LightingPresets::default()
+            .find(name)
+            .unwrap_or_else(|| fail(format!("unknown --lighting '{name}'")));
+        app.insert_resource(Lighting(preset));
+    }
+
+    match net_mode {
+        NetMode::Local if args.iter().any(|arg| arg == "--headless") => {
+            let ticks = match arg_value(&args, "--ticks") {
+                Some(ticks) => ticks
+                    .parse()
+                    .unwrap_or_else(|_| fail(format!("invalid --ticks '{ticks}'"))),
+                None => 600,
+            };
+
+            let script = match arg_value(&args, "--script") {
+                Some(path) => InputScript::load(Path::new(path)).unwrap_or_else(|error| fail(error)),
+                None => InputScript::default(),
+            };
+
+            // run as fast as possible, but every frame advances exactly one gameplay tick
+            add_headless_plugins(&mut app, Duration::ZERO);
+            app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK_DURATION))
+                .insert_resource(PlayerCount(player_count))
+                .insert_resource(BoardSeed(board_seed))
+                .insert_resource(board_size)
+                .insert_resource(script)
+                .insert_resource(HeadlessRun::new(ticks))
+                .add_systems(PreUpdate, apply_input_script)
+                .add_systems(Update, finish_headless_run);
+        }
+        NetMode::Local => {
+            let (mut player_count, mut board_seed, mut board_size) =
+                (player_count, board_seed, board_size);
+
+            add_window_plugins(&mut app, &settings);
+
+            // a replay brings its own board, players and difficulty
+            if let Some(path) = arg_value(&args, "--replay") {
+                let replay = Replay::load(Path::new(path)).unwrap_or_else(|error| fail(error));
+                player_count = replay.player_count;
+                board_seed = replay.board_seed;
+                board_size = replay.board_size;
+                settings.gameplay = replay.gameplay.clone();
+
+                app.insert_resource(ReplayPlayback::new(replay))
+                    .add_systems(First, playback_frame_time.before(TimeSystem))
+                    .add_systems(
+                        PreUpdate,
+                        playback_input
+                            .after(track_cursor)
+                            .before(read_keyboard_input),
+                    );
+            }
+
+            if let Some(path) = arg_value(&args, "--record") {
+                let replay =
+                    Replay::new(board_seed, board_size, player_count, settings.gameplay.clone());
+                app.insert_resource(ReplayRecorder::new(PathBuf::from(path), replay))
+                .add_systems(PreUpdate, record_input.after(track_cursor))
+                .add_systems(Last, save_recording);
+            }
+
+            let save_file = arg_value(&args, "--save-file").unwrap_or("savegame.ron");
+
+            app.insert_resource(PlayerCount(player_count))
+                .insert_resource(BoardSeed(board_seed))
+                .insert_resource(board_size)
+                .insert_resource(SaveFile(PathBuf::from(save_file)))
+                .add_systems(Update, save_game_on_key.run_if(in_state(GameState::Playing)));
+
+            // a loaded game carries on straight away
+            if saved.is_none() {
+                app.insert_state(GameState::Menu);
+            }
+            add_menu_systems(&mut app, saved.as_ref());
+        }
+        NetMode::Server(addr) => {
+            let server = NetServer::bind(addr)
+                .unwrap_or_else(|error| fail(format!("Failed to start server on {addr}: {error}")));
+
+            add_headless_plugins(&mut app, TICK_DURATION);
+            app.insert_resource(server)
+                // aliens are added as clients join
+                .insert_resource(PlayerCount(0))
+                .insert_resource(BoardSeed(board_seed))
+                .insert_resource(board_size)
+                .add_systems(PreUpdate, receive_client_messages)
+                .add_systems(Update, send_snapshots);
+        }
+        NetMode::Client(addr) => {
+            let (client, board_seed, board_size) = NetClient::connect(addr)
+                .unwrap_or_else(|error| fail(format!("Failed to join server at {addr}: {error}")));
+
+            add_window_plugins(&mut app, &settings);
+            app.insert_resource(client)
+                .insert_resource(PlayerCount(0))
+                .insert_resource(board_seed)
+                .insert_resource(board_size)
+                .add_systems(
+                    Startup,
+                    setup_client_players
+                        .after(setup_game_state)
+                        .before(spawn_game_board),
+                )
+                .add_systems(PreUpdate, send_client_input.after(read_keyboard_input))
+                .add_systems(
+                    Update,
+                    (
+                        receive_snapshots,
+                        apply_snapshots
+                            .after(receive_snapshots)
+                            .before(update_tracking_camera)
+                            .before(update_display),
+                    ),
+                );
+        }
+    }
+
+    if let Some(saved) = saved {
+        app.insert_resource(LoadedSave(saved));
+    }
+
+    app.insert_resource(EnemyCount(settings.gameplay.difficulty.enemy_count()))
+        .insert_resource(PickupLimit(3))
+        .insert_resource(settings);
+
+    add_gameplay_systems(&mut app);
+
+    app.run();
+}
+
+// the board, characters and cake, shared by every mode including the headless server
+fn add_gameplay_systems(app: &mut App) {
+    app.init_resource::<Game>()
+        .init_resource::<Settings>()
+        .init_resource::<BoardSize>()
+        .init_resource::<EnemyCount>()
+        .init_resource::<PickupKinds>()
+        .init_resource::<PickupLimit>()
+        .init_resource::<PickupSpawnTimer>()
+        .init_resource::<PickupRespawnTimers>()
+        .insert_resource(Time::<Fixed>::from_duration(TICK_DURATION))
+        .add_event::<ScorePopup>()
+        .add_event::<SoundEffect>()
+        .add_event::<CakeEaten>()
         .init_state::<GameState>()
         .add_systems(
             Startup,
             (
-                // spawn_lines,
-                // spawn_cylinders,
-                spawn_camera,
-                spawn_point_light,
                 setup_game_state,
-                spawn_game_board.after(setup_game_state),
+                restore_saved_game
+                    .after(setup_game_state)
+                    .run_if(resource_exists::<LoadedSave>),
+                spawn_game_board.after(restore_saved_game),
                 spawn_character.after(spawn_game_board),
-                spawn_cake.after(spawn_game_board),
+                // clients see the server's creatures
+                spawn_enemies
+                    .after(spawn_game_board)
+                    .run_if(is_authoritative),
+                // and the server's pickups
+                setup_pickups
+                    .after(spawn_game_board)
+                    .run_if(is_authoritative),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            (
+                advance_simulated_transforms,
+                advance_game_clock,
+                move_player.after(advance_simulated_transforms),
+                collect_pickups.after(move_player),
+                move_pickups.after(collect_pickups).after(advance_game_clock),
+                respawn_pickups.after(move_pickups),
+                spawn_pickups.after(respawn_pickups),
+                expire_effects.before(move_player),
+                decay_multipliers.after(advance_game_clock).before(move_player),
+                update_enemies.after(collect_pickups).after(advance_game_clock),
+                check_round_over
+                    .after(advance_game_clock)
+                    .run_if(resource_exists::<RoundLength>),
+                // focus_camera,
+                // rotate_bonus,
+                // scoreboard_system,
+                // spawn_bonus,
+            )
+                .run_if(in_state(GameState::Playing))
+                .run_if(is_authoritative),
+        )
+        // before the gameplay ticks of the frame
+        .add_systems(
+            PreUpdate,
+            apply_cake_movement.run_if(resource_changed::<Settings>),
+        )
+        // clients place entities straight from the server snapshots instead
+        .add_systems(Update, interpolate_transforms.run_if(is_authoritative));
+}
+
+// window, cameras, lights, input and the scoreboard
+fn add_window_plugins(app: &mut App, settings: &Settings) {
+    // .add_plugins((DefaultPlugins, MaterialPlugin::<LineMaterial>::default()))
+    app.add_plugins(DefaultPlugins.set(WindowPlugin {
+        primary_window: Some(primary_window(settings)),
+        ..default()
+    }))
+        .add_systems(
+            Startup,
+            (
+                // spawn_lines,
+                // spawn_cylinders,
+                spawn_camera.after(spawn_character),
+                spawn_lights,
                 spawn_scoreboard.after(setup_game_state),
+                setup_particles,
             ),
         )
         // .add_systems(OnEnter(GameState::Playing), (
         //         setup_cameras.on_startup(),
         // update_display,
         // ))
+        .init_resource::<CursorPosition>()
+        .init_resource::<HoveredCell>()
+        .init_resource::<LightingPresets>()
+        .init_resource::<Lighting>()
+        .init_resource::<DayNight>()
         .add_systems(
             Update,
             (
-                move_player,
-                update_tracking_camera.after(move_player),
-                update_cake.after(move_player),
-                update_display.after(move_player),
-                // focus_camera,
-                // rotate_bonus,
-                // scoreboard_system,
-                // spawn_bonus,
+                switch_lighting.run_if(in_state(GameState::Playing)),
+                apply_lighting
+                    .after(switch_lighting)
+                    .run_if(resource_changed::<Lighting>),
+                update_day_night.after(apply_lighting),
+                update_headlamps.after(update_day_night),
+            ),
+        )
+        .add_systems(
+            PreUpdate,
+            (
+                track_cursor.after(InputSystem),
+                read_keyboard_input
+                    .after(InputSystem)
+                    .run_if(in_state(GameState::Playing)),
+            ),
+        )
+        .add_systems(
+            Update,
+            (
+                update_tracking_camera.after(interpolate_transforms),
+                update_display,
+                // clients don't walk paths, the server only hears their keys
+                pick_cell.run_if(is_authoritative),
+                draw_hovered_cell.after(pick_cell),
             )
                 .run_if(in_state(GameState::Playing)),
         )
@@ -70,6 +370,108 @@ fn main() {
         //     gameover_keyboard.in_set(OnUpdate(GameState::GameOver)),
         //     teardown.in_schedule(OnExit(GameState::GameOver)),
         // ))
-        .add_systems(Update, bevy::window::close_on_esc)
-        .run();
+        .add_systems(
+            Update,
+            (apply_window_settings, apply_shadow_settings).run_if(resource_changed::<Settings>),
+        )
+        .add_systems(Update, (apply_carried_light_shadows, update_shadow_cascades))
+        .add_systems(Update, set_camera_viewports)
+        .add_systems(Update, (spawn_score_popups, animate_score_popups.after(spawn_score_popups)))
+        .add_systems(
+            Update,
+            (
+                burst_eaten_cakes,
+                emit_particles.after(burst_eaten_cakes),
+                update_particles.after(emit_particles),
+            ),
+        )
+        // music and sound effects, silent when there is no audio device
+        .init_resource::<MusicPlayer>()
+        .add_systems(
+            Update,
+            (play_music, play_sound_effects, despawn_finished_sounds).run_if(has_audio),
+        )
+        .add_systems(Update, bevy::window::close_on_esc);
+}
+
+// title screen, rounds that end and the high score table, for the local windowed game
+fn add_menu_systems(app: &mut App, saved: Option<&SavedGame>) {
+    let high_score_file = HighScoreFile::default();
+    let level_directory = LevelDirectory::default();
+
+    // a loaded game carries on at its own level, without level files the rounds just run out
+    let saved_level = saved.and_then(|saved| saved.level);
+    let number = saved_level.map_or(1, |level| level.number);
+    match level_directory.load(number) {
+        Some(mut level) => {
+            level.cakes_at_start = saved_level.map_or(0, |level| level.cakes_at_start);
+            app.insert_resource(level);
+        }
+        None => warn!("No level {number} in {}", level_directory.0.display()),
+    }
+
+    // and in its own mode, otherwise until another mode is picked on the title screen
+    let mode = saved.map_or(GameMode::default(), |saved| saved.mode);
+    app.insert_resource(mode);
+    if let Some(length) = mode.round_length() {
+        app.insert_resource(RoundLength(length));
+    }
+
+    app.insert_resource(HighScores::load(&high_score_file.0))
+        .insert_resource(high_score_file)
+        .insert_resource(level_directory)
+        .add_systems(OnEnter(GameState::Menu), spawn_menu)
+        .add_systems(OnExit(GameState::Menu), despawn_screen)
+        .add_systems(OnEnter(GameState::HighScores), spawn_high_scores)
+        .add_systems(OnExit(GameState::HighScores), despawn_screen)
+        .add_systems(OnEnter(GameState::Settings), spawn_settings_menu)
+        .add_systems(OnExit(GameState::Settings), despawn_screen)
+        .add_systems(OnEnter(GameState::LevelComplete), spawn_level_complete)
+        .add_systems(OnExit(GameState::LevelComplete), despawn_screen)
+        .add_systems(OnEnter(GameState::GameOver), start_name_entry)
+        .add_systems(OnExit(GameState::GameOver), (despawn_screen, restart_game))
+        .add_systems(
+            Update,
+            (
+                menu_keyboard.run_if(in_state(GameState::Menu)),
+                high_scores_keyboard.run_if(in_state(GameState::HighScores)),
+                game_over_keyboard.run_if(in_state(GameState::GameOver)),
+                settings_keyboard.run_if(in_state(GameState::Settings)),
+                level_complete_keyboard.run_if(in_state(GameState::LevelComplete)),
+                end_round_keyboard.run_if(in_state(GameState::Playing)),
+                update_exit_marker.run_if(resource_changed::<CurrentLevel>),
+            ),
+        )
+        .add_systems(
+            FixedUpdate,
+            check_objectives
+                .after(collect_pickups)
+                .after(move_pickups)
+                .run_if(in_state(GameState::Playing))
+                .run_if(resource_exists::<CurrentLevel>)
+                .run_if(resource_equals(GameMode::Levels)),
+        );
+}
+
+// no window or renderer, scenes are still spawned so entities match the windowed game
+fn add_headless_plugins(app: &mut App, frame_wait: Duration) {
+    app.add_plugins((
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(frame_wait)),
+        bevy::log::LogPlugin::default(),
+        bevy::transform::TransformPlugin,
+        bevy::hierarchy::HierarchyPlugin,
+        AssetPlugin::default(),
+        bevy::scene::ScenePlugin,
+    ));
+}
+
+// value following a command line flag, e.g. `--ticks 600`
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.get(index + 1).map(String::as_str)
+}
+
+fn fail(message: impl Display) -> ! {
+    eprintln!("{message}");
+    std::process::exit(1);
 }
@@ -0,0 +1,423 @@
+use bevy::prelude::*;
+use bevy::window::ReceivedCharacter;
+
+use crate::enemy::enemy_start;
+use crate::game_state::*;
+use crate::high_scores::*;
+use crate::level::*;
+use crate::scoring::score_breakdown;
+use crate::settings::{Settings, SettingsFile};
+use crate::pickup::{place_respawning_pickups, PickupKinds, PickupRng};
+use crate::spawn::{
+    random_board, spawn_board_tiles, spawn_enemy_entity, spawn_player_entity, BoardTile,
+};
+
+/// Tags the full screen text of the menu, game over and high score screens
+#[derive(Component)]
+pub struct ScreenText;
+
+// centered text over a darkened game, removed again by despawn_screen
+fn spawn_screen(commands: &mut Commands, asset_server: &AssetServer, text: String) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.),
+                    height: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                ..default()
+            },
+            ScreenText,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 40.0,
+                        color: Color::rgb(0.5, 0.5, 1.0),
+                    },
+                ),
+                ScreenText,
+            ));
+        });
+}
+
+pub fn despawn_screen(mut commands: Commands, query: Query<Entity, With<ScreenText>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn set_screen_text(query: &mut Query<&mut Text, With<ScreenText>>, value: String) {
+    if let Ok(mut text) = query.get_single_mut() {
+        text.sections[0].value = value;
+    }
+}
+
+pub fn spawn_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    info!("Showing menu");
+
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        "Alien Explorer\n\nEnter: play the levels\nT: time attack\nE: endless\nH: high scores\nS: settings\nEsc: quit"
+            .to_string(),
+    );
+}
+
+pub fn menu_keyboard(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let mode = if keyboard_input.just_pressed(KeyCode::Enter) {
+        Some(GameMode::Levels)
+    } else if keyboard_input.just_pressed(KeyCode::KeyT) {
+        Some(GameMode::TimeAttack)
+    } else if keyboard_input.just_pressed(KeyCode::KeyE) {
+        Some(GameMode::Endless)
+    } else {
+        None
+    };
+
+    if let Some(mode) = mode {
+        start_mode(&mut commands, mode);
+        next_state.set(GameState::Playing);
+    } else if keyboard_input.just_pressed(KeyCode::KeyH) {
+        next_state.set(GameState::HighScores);
+    } else if keyboard_input.just_pressed(KeyCode::KeyS) {
+        next_state.set(GameState::Settings);
+    }
+}
+
+// Q gives up, the only way an endless round ends
+pub fn end_round_keyboard(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyQ) {
+        info!("Round ended");
+        next_state.set(GameState::GameOver);
+    }
+}
+
+pub fn spawn_high_scores(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    high_scores: Res<HighScores>,
+) {
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        format!(
+            "High Scores\n\n{}\n\nEnter: menu",
+            high_score_table(&high_scores)
+        ),
+    );
+}
+
+pub fn high_scores_keyboard(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::Menu);
+    }
+}
+
+pub fn spawn_level_complete(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    level: Res<CurrentLevel>,
+    directory: Res<LevelDirectory>,
+) {
+    let next = if directory.level_path(level.number + 1).exists() {
+        "Enter: next level"
+    } else {
+        "That was the last level!\n\nEnter: continue"
+    };
+
+    spawn_screen(
+        &mut commands,
+        &asset_server,
+        format!("Level {} complete\n{}\n\n{next}", level.number, level.level.name),
+    );
+}
+
+// on to the next level, or to the game over screen after the last one
+pub fn level_complete_keyboard(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    directory: Res<LevelDirectory>,
+    mut level: ResMut<CurrentLevel>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::Enter) {
+        return;
+    }
+
+    match directory.load(level.number + 1) {
+        Some(next) => {
+            *level = next;
+            start_level(&mut game, &mut level);
+            next_state.set(GameState::Playing);
+        }
+        None => next_state.set(GameState::GameOver),
+    }
+}
+
+/// Row of the settings menu being changed
+#[derive(Resource, Default)]
+pub struct SettingsCursor(pub usize);
+
+pub fn spawn_settings_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SettingsCursor::default());
+    spawn_screen(&mut commands, &asset_server, String::new());
+}
+
+// up and down pick a row, left and right change it, enter saves and goes back to the menu
+pub fn settings_keyboard(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut cursor: ResMut<SettingsCursor>,
+    mut settings: ResMut<Settings>,
+    settings_file: Res<SettingsFile>,
+    mut board_size: ResMut<BoardSize>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text_query: Query<&mut Text, With<ScreenText>>,
+) {
+    let rows = settings.menu_rows().len();
+
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
+        cursor.0 = (cursor.0 + rows - 1) % rows;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        cursor.0 = (cursor.0 + 1) % rows;
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowLeft) {
+        settings.change(cursor.0, -1);
+    }
+    if keyboard_input.just_pressed(KeyCode::ArrowRight) {
+        settings.change(cursor.0, 1);
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        match settings.save(&settings_file.0) {
+            Ok(()) => info!("Saved settings to {}", settings_file.0.display()),
+            Err(error) => error!("{error}"),
+        }
+        // the board is rebuilt at the new size when the next round starts
+        *board_size = settings.board;
+        next_state.set(GameState::Menu);
+    }
+
+    let rows: Vec<String> = settings
+        .menu_rows()
+        .iter()
+        .enumerate()
+        .map(|(row, text)| {
+            let marker = if row == cursor.0 { ">" } else { " " };
+            format!("{marker} {text}")
+        })
+        .collect();
+
+    set_screen_text(
+        &mut text_query,
+        format!(
+            "Settings\n\n{}\n\nArrows: change  Enter: save",
+            rows.join("\n")
+        ),
+    );
+}
+
+// asks every player with a good enough score for a name, best score first
+pub fn start_name_entry(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game: Res<Game>,
+    high_scores: Res<HighScores>,
+) {
+    let mut players: Vec<usize> = (0..game.players.len())
+        .filter(|index| high_scores.qualifies(game.players[*index].score))
+        .collect();
+    players.sort_by_key(|index| -game.players[*index].score);
+
+    commands.insert_resource(NameEntry {
+        players,
+        name: String::new(),
+    });
+    spawn_screen(&mut commands, &asset_server, String::new());
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn game_over_keyboard(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut characters: EventReader<ReceivedCharacter>,
+    game: Res<Game>,
+    mut entry: ResMut<NameEntry>,
+    mut high_scores: ResMut<HighScores>,
+    high_score_file: Res<HighScoreFile>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut text_query: Query<&mut Text, With<ScreenText>>,
+) {
+    let Some(&index) = entry.players.first() else {
+        characters.clear();
+        if keyboard_input.just_pressed(KeyCode::Enter) {
+            next_state.set(GameState::HighScores);
+        }
+        set_screen_text(
+            &mut text_query,
+            format!("Game Over\n\n{}\nEnter: high scores", final_scores(&game)),
+        );
+        return;
+    };
+
+    for event in characters.read() {
+        for c in event.char.chars() {
+            entry.type_char(c);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Backspace) {
+        entry.backspace();
+    }
+
+    let player = &game.players[index];
+
+    if keyboard_input.just_pressed(KeyCode::Enter) {
+        let name = match entry.name.trim() {
+            "" => format!("Player {}", index + 1),
+            name => name.to_string(),
+        };
+        high_scores.insert(HighScore {
+            name,
+            score: player.score,
+            cakes: player.cake_eaten,
+        });
+
+        entry.players.remove(0);
+        entry.name.clear();
+
+        if entry.players.is_empty() {
+            match high_scores.save(&high_score_file.0) {
+                Ok(()) => info!("Saved high scores to {}", high_score_file.0.display()),
+                Err(error) => error!("{error}"),
+            }
+            next_state.set(GameState::HighScores);
+        }
+        return;
+    }
+
+    set_screen_text(
+        &mut text_query,
+        format!(
+            "Game Over\n\n{}\nNew high score for player {}!\nName: {}_",
+            final_scores(&game),
+            index + 1,
+            entry.name
+        ),
+    );
+}
+
+fn final_scores(game: &Game) -> String {
+    game.players
+        .iter()
+        .enumerate()
+        .map(|(index, player)| {
+            format!(
+                "player {}: score: {} cakes: {}\n{}\n",
+                index + 1,
+                player.score,
+                player.cake_eaten,
+                score_breakdown(player)
+            )
+        })
+        .collect()
+}
+
+// puts everyone back at the start for the next round
+#[allow(clippy::too_many_arguments)]
+pub fn restart_game(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    mut rng: ResMut<PickupRng>,
+    board_seed: Res<BoardSeed>,
+    board_size: Res<BoardSize>,
+    tiles: Query<Entity, With<BoardTile>>,
+    directory: Option<Res<LevelDirectory>>,
+    mut level: Option<ResMut<CurrentLevel>>,
+) {
+    info!("Restarting game");
+
+    if *board_size != game.board.size() {
+        info!("Rebuilding the board at {} x {}", board_size.width, board_size.height);
+        for tile in &tiles {
+            commands.entity(tile).despawn_recursive();
+        }
+        game.board = random_board(*board_size, board_seed.0);
+        spawn_board_tiles(&mut commands, &asset_server, &game.board);
+    }
+
+    let player_count = game.players.len();
+    for index in 0..player_count {
+        if let Some(entity) = game.players[index].entity {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let (i, j) = start_position(index, player_count, game.board.size());
+        game.players[index] = Player::new(i, j, game.players[index].controls);
+        game.players[index].entity = Some(spawn_player_entity(
+            &mut commands,
+            &asset_server,
+            &game,
+            index,
+        ));
+    }
+
+    for index in 0..game.enemies.len() {
+        if let Some(entity) = game.enemies[index].entity {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        game.enemies[index] = enemy_start(index, &game.board);
+        game.enemies[index].entity = Some(spawn_enemy_entity(
+            &mut commands,
+            &asset_server,
+            &game,
+            index,
+        ));
+    }
+
+    for pickup in game.pickups.drain(..) {
+        if let Some(entity) = pickup.entity {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    place_respawning_pickups(
+        &mut commands,
+        &asset_server,
+        &mut game,
+        &kinds,
+        &mut rng.0,
+        settings.gameplay.cake_movement,
+    );
+
+    game.elapsed = default();
+    game.time_bonus = default();
+
+    // back to the first level
+    if let (Some(directory), Some(level)) = (directory, level.as_mut()) {
+        if let Some(first) = directory.load(1) {
+            **level = first;
+            start_level(&mut game, level);
+        }
+    }
+}
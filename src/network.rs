@@ -0,0 +1,762 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::Cell;
+use crate::enemy::{Behaviour, Enemy};
+use crate::game_state::*;
+use crate::pickup::{ActiveEffect, Pickup, PickupKinds, PICKUP_LIFT};
+use crate::spawn::{spawn_enemy_entity, spawn_pickup_entity, spawn_player_entity};
+
+pub const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:5000";
+
+/// Big enough for a snapshot with a handful of players while staying under a typical MTU
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+/// Most aliens a server takes. Without their effects each alien is 28 bytes of a snapshot and
+/// the clock, three creatures and four pickups another 150 or so, so 37 would fit; this leaves
+/// room for a few more pickups.
+pub const MAX_PLAYERS: usize = 32;
+
+// how far in the past clients render the aliens so there are two snapshots to blend between
+const INTERPOLATION_DELAY: f32 = 0.1;
+
+// clients that have not sent anything for this long are dropped by the server
+const CLIENT_TIMEOUT: f32 = 5.0;
+
+/// How this process takes part in the game, picked from the command line
+pub enum NetMode {
+    Local,
+    Server(SocketAddr),
+    Client(SocketAddr),
+}
+
+impl NetMode {
+    /// `--server [addr]` runs the headless authoritative server, `--connect [addr]` joins one
+    pub fn from_args(args: &[String]) -> Result<NetMode, String> {
+        let addr_after = |flag: &str| -> Option<Result<SocketAddr, String>> {
+            let index = args.iter().position(|arg| arg == flag)?;
+            let addr = args
+                .get(index + 1)
+                .filter(|arg| !arg.starts_with("--"))
+                .map(String::as_str)
+                .unwrap_or(DEFAULT_SERVER_ADDR);
+            Some(
+                addr.parse()
+                    .map_err(|error| format!("invalid address '{addr}' for {flag}: {error}")),
+            )
+        };
+
+        if let Some(addr) = addr_after("--server") {
+            addr.map(NetMode::Server)
+        } else if let Some(addr) = addr_after("--connect") {
+            addr.map(NetMode::Client)
+        } else {
+            Ok(NetMode::Local)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ClientMessage {
+    Hello,
+    Input { sequence: u32, input: PlayerInput },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ServerMessage {
+    Welcome {
+        player: usize,
+        board_seed: u64,
+        board_size: BoardSize,
+    },
+    Snapshot(Snapshot),
+    /// The client can't join, e.g. when the game already has `MAX_PLAYERS` aliens
+    Rejected { reason: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerSnapshot {
+    pub i: f32,
+    pub j: f32,
+    pub rotation: f32,
+    pub score: i32,
+    pub cake_eaten: u32,
+    pub effects: Vec<ActiveEffect>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EnemySnapshot {
+    pub i: f32,
+    pub j: f32,
+    pub rotation: f32,
+}
+
+/// The authoritative state of the board the server sends every tick
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub tick: u32,
+    pub players: Vec<PlayerSnapshot>,
+    pub enemies: Vec<EnemySnapshot>,
+    /// Kind and position of every pickup on the board, the cake among them
+    pub pickups: Vec<(usize, f32, f32)>,
+    pub elapsed: Duration,
+}
+
+impl Snapshot {
+    pub fn from_game(tick: u32, game: &Game) -> Snapshot {
+        Snapshot {
+            tick,
+            players: game
+                .players
+                .iter()
+                .map(|player| PlayerSnapshot {
+                    i: player.i,
+                    j: player.j,
+                    rotation: player.rotation,
+                    score: player.score,
+                    cake_eaten: player.cake_eaten,
+                    effects: player.effects.clone(),
+                })
+                .collect(),
+            enemies: game
+                .enemies
+                .iter()
+                .map(|enemy| EnemySnapshot {
+                    i: enemy.i,
+                    j: enemy.j,
+                    rotation: enemy.rotation,
+                })
+                .collect(),
+            pickups: game
+                .pickups
+                .iter()
+                .map(|pickup| (pickup.kind, pickup.i, pickup.j))
+                .collect(),
+            elapsed: game.elapsed,
+        }
+    }
+}
+
+fn encode<T: Serialize>(message: &T) -> io::Result<Vec<u8>> {
+    bincode::serialize(message).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Encode a snapshot to fit in one packet. When it is too big the players' effects, which are
+/// only shown on the scoreboard, are left out, and the error says when it still doesn't fit.
+pub fn encode_snapshot(snapshot: Snapshot) -> Result<Vec<u8>, String> {
+    let mut message = ServerMessage::Snapshot(snapshot);
+    let bytes = encode(&message).map_err(|error| error.to_string())?;
+    if bytes.len() <= MAX_PACKET_SIZE {
+        return Ok(bytes);
+    }
+
+    if let ServerMessage::Snapshot(snapshot) = &mut message {
+        for player in snapshot.players.iter_mut() {
+            player.effects.clear();
+        }
+    }
+    let bytes = encode(&message).map_err(|error| error.to_string())?;
+    if bytes.len() <= MAX_PACKET_SIZE {
+        Ok(bytes)
+    } else {
+        Err(format!(
+            "snapshot is {} bytes, more than the {MAX_PACKET_SIZE} that fit in a packet",
+            bytes.len()
+        ))
+    }
+}
+
+/// Gameplay systems only run where the game state is authoritative, i.e. not on a client
+pub fn is_authoritative(client: Option<Res<NetClient>>) -> bool {
+    client.is_none()
+}
+
+struct RemoteClient {
+    player: usize,
+    last_sequence: u32,
+    last_heard: f32,
+}
+
+/// Server side of the connection, owns the socket and the clients that joined
+#[derive(Resource)]
+pub struct NetServer {
+    socket: UdpSocket,
+    clients: HashMap<SocketAddr, RemoteClient>,
+    tick: u32,
+}
+
+impl NetServer {
+    pub fn bind(addr: SocketAddr) -> io::Result<NetServer> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(NetServer {
+            socket,
+            clients: HashMap::new(),
+            tick: 0,
+        })
+    }
+}
+
+// handle joins and inputs from clients, giving each client its own alien
+pub fn receive_client_messages(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    board_seed: Res<BoardSeed>,
+    board_size: Res<BoardSize>,
+    mut server: ResMut<NetServer>,
+    mut game: ResMut<Game>,
+) {
+    let now = time.elapsed_seconds();
+    let server = &mut *server;
+    let mut buffer = [0; MAX_PACKET_SIZE];
+
+    loop {
+        let (size, addr) = match server.socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                warn!("Failed to receive from clients: {error}");
+                break;
+            }
+        };
+
+        let Ok(message) = bincode::deserialize::<ClientMessage>(&buffer[..size]) else {
+            warn!("Ignoring malformed packet from {addr}");
+            continue;
+        };
+
+        match message {
+            ClientMessage::Hello => {
+                if !server.clients.contains_key(&addr) {
+                    // reuse the alien of a client that timed out before adding a new one
+                    let free = (0..game.players.len()).find(|index| {
+                        !server.clients.values().any(|client| client.player == *index)
+                    });
+                    let player = match free {
+                        Some(index) => index,
+                        None if game.players.len() < MAX_PLAYERS => {
+                            let (i, j) = board_size.center();
+                            game.players.push(Player::new(i, j, None));
+                            let index = game.players.len() - 1;
+                            let entity =
+                                spawn_player_entity(&mut commands, &asset_server, &game, index);
+                            game.players[index].entity = Some(entity);
+                            index
+                        }
+                        // more aliens wouldn't fit in a snapshot
+                        None => {
+                            info!("Turning away {addr}, the game is full");
+                            let rejected = ServerMessage::Rejected {
+                                reason: format!("the game is full ({MAX_PLAYERS} players)"),
+                            };
+                            if let Err(error) = encode(&rejected)
+                                .and_then(|bytes| server.socket.send_to(&bytes, addr))
+                            {
+                                warn!("Failed to turn away {addr}: {error}");
+                            }
+                            continue;
+                        }
+                    };
+                    info!("Player {} joined from {addr}", player + 1);
+
+                    server.clients.insert(
+                        addr,
+                        RemoteClient {
+                            player,
+                            last_sequence: 0,
+                            last_heard: now,
+                        },
+                    );
+                }
+
+                // hellos are repeated until the welcome gets through
+                let client = server.clients.get_mut(&addr).unwrap();
+                client.last_heard = now;
+                let player = client.player;
+
+                let welcome = ServerMessage::Welcome {
+                    player,
+                    board_seed: board_seed.0,
+                    board_size: *board_size,
+                };
+                if let Err(error) = encode(&welcome).and_then(|bytes| server.socket.send_to(&bytes, addr)) {
+                    warn!("Failed to welcome {addr}: {error}");
+                }
+            }
+            ClientMessage::Input { sequence, input } => {
+                // inputs can arrive out of order, only the newest one counts
+                if let Some(client) = server.clients.get_mut(&addr) {
+                    if sequence > client.last_sequence {
+                        client.last_sequence = sequence;
+                        client.last_heard = now;
+                        game.players[client.player].input = input;
+                    }
+                }
+            }
+        }
+    }
+
+    server.clients.retain(|addr, client| {
+        let alive = now - client.last_heard < CLIENT_TIMEOUT;
+        if !alive {
+            info!("Player {} at {addr} timed out", client.player + 1);
+            game.players[client.player].input = PlayerInput::default();
+        }
+        alive
+    });
+}
+
+// `failing` keeps an oversized game from warning every tick
+pub fn send_snapshots(
+    mut server: ResMut<NetServer>,
+    game: Res<Game>,
+    mut failing: Local<bool>,
+) {
+    server.tick = server.tick.wrapping_add(1);
+
+    let bytes = match encode_snapshot(Snapshot::from_game(server.tick, &game)) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            if !*failing {
+                warn!("Not sending snapshots, the clients will stop updating: {error}");
+                *failing = true;
+            }
+            return;
+        }
+    };
+    if *failing {
+        info!("Sending snapshots again");
+        *failing = false;
+    }
+
+    for addr in server.clients.keys() {
+        if let Err(error) = server.socket.send_to(&bytes, addr) {
+            warn!("Failed to send snapshot to {addr}: {error}");
+        }
+    }
+}
+
+/// Client side of the connection, keeps the recent snapshots to interpolate between
+#[derive(Resource)]
+pub struct NetClient {
+    socket: UdpSocket,
+    /// Index into `Game.players` of the alien this client controls
+    pub player: usize,
+    sequence: u32,
+    last_tick: u32,
+    /// Received snapshots and the client time they arrived at, oldest first
+    snapshots: VecDeque<(f32, Snapshot)>,
+}
+
+impl NetClient {
+    /// Say hello to the server and wait for it to assign us an alien and describe the board
+    pub fn connect(server: SocketAddr) -> io::Result<(NetClient, BoardSeed, BoardSize)> {
+        let local: SocketAddr = if server.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+
+        let hello = encode(&ClientMessage::Hello)?;
+        let mut buffer = [0; MAX_PACKET_SIZE];
+
+        // the hello or the welcome may get lost, so keep asking for a few seconds
+        for _ in 0..10 {
+            socket.send(&hello)?;
+
+            match socket.recv(&mut buffer) {
+                Ok(size) => match bincode::deserialize(&buffer[..size]) {
+                    Ok(ServerMessage::Welcome {
+                        player,
+                        board_seed,
+                        board_size,
+                    }) => {
+                        socket.set_read_timeout(None)?;
+                        socket.set_nonblocking(true)?;
+
+                        let client = NetClient {
+                            socket,
+                            player,
+                            sequence: 0,
+                            last_tick: 0,
+                            snapshots: VecDeque::new(),
+                        };
+                        return Ok((client, BoardSeed(board_seed), board_size.clamped()));
+                    }
+                    Ok(ServerMessage::Rejected { reason }) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::ConnectionRefused,
+                            format!("server at {server} turned us away: {reason}"),
+                        ));
+                    }
+                    // snapshots sent before the welcome got through
+                    _ => {}
+                },
+                // refused means the server is not up yet
+                Err(error)
+                    if matches!(
+                        error.kind(),
+                        io::ErrorKind::WouldBlock
+                            | io::ErrorKind::TimedOut
+                            | io::ErrorKind::ConnectionRefused
+                    ) =>
+                {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no answer from server at {server}"),
+        ))
+    }
+}
+
+// make room for every alien up to ours, only our own alien is driven by this keyboard
+pub fn setup_client_players(
+    client: Res<NetClient>,
+    board_size: Res<BoardSize>,
+    mut game: ResMut<Game>,
+) {
+    info!("Joined as player {}", client.player + 1);
+
+    let (i, j) = board_size.center();
+    game.players = (0..=client.player)
+        .map(|index| {
+            let controls = (index == client.player).then_some(PlayerControls::ARROWS);
+            Player::new(i, j, controls)
+        })
+        .collect();
+}
+
+pub fn send_client_input(mut client: ResMut<NetClient>, game: Res<Game>) {
+    let Some(player) = game.players.get(client.player) else {
+        return;
+    };
+
+    client.sequence += 1;
+    let message = ClientMessage::Input {
+        sequence: client.sequence,
+        input: player.input,
+    };
+
+    if let Err(error) = encode(&message).and_then(|bytes| client.socket.send(&bytes)) {
+        warn!("Failed to send input: {error}");
+    }
+}
+
+pub fn receive_snapshots(time: Res<Time>, mut client: ResMut<NetClient>) {
+    let now = time.elapsed_seconds();
+    let mut buffer = [0; MAX_PACKET_SIZE];
+
+    loop {
+        let size = match client.socket.recv(&mut buffer) {
+            Ok(size) => size,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                warn!("Failed to receive from server: {error}");
+                break;
+            }
+        };
+
+        match bincode::deserialize(&buffer[..size]) {
+            // late snapshots are older than what we already have, drop them
+            Ok(ServerMessage::Snapshot(snapshot)) => {
+                if snapshot.tick > client.last_tick {
+                    client.last_tick = snapshot.tick;
+                    client.snapshots.push_back((now, snapshot));
+                }
+            }
+            Ok(ServerMessage::Welcome { .. } | ServerMessage::Rejected { .. }) => {}
+            // a snapshot bigger than the buffer arrives cut short
+            Err(error) => {
+                warn!("Ignoring malformed packet from the server ({size} bytes): {error}");
+            }
+        }
+    }
+}
+
+// move every alien and the cake to where the snapshots say they were a moment ago
+pub fn apply_snapshots(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut client: ResMut<NetClient>,
+    mut game: ResMut<Game>,
+    mut transforms: Query<&mut Transform>,
+    kinds: Res<PickupKinds>,
+) {
+    let render_time = time.elapsed_seconds() - INTERPOLATION_DELAY;
+
+    while client.snapshots.len() > 2 && client.snapshots[1].0 <= render_time {
+        client.snapshots.pop_front();
+    }
+
+    let (Some((from_time, from)), Some((_, latest))) =
+        (client.snapshots.front(), client.snapshots.back())
+    else {
+        return;
+    };
+    let (to_time, to) = client.snapshots.get(1).unwrap_or(&client.snapshots[0]);
+    let t = if to_time > from_time {
+        ((render_time - from_time) / (to_time - from_time)).clamp(0.0, 1.0)
+    } else {
+        1.0
+    };
+
+    // aliens of clients that joined after us
+    while game.players.len() < latest.players.len() {
+        let (i, j) = game.board.size().center();
+        game.players.push(Player::new(i, j, None));
+        let index = game.players.len() - 1;
+        let entity = spawn_player_entity(&mut commands, &asset_server, &game, index);
+        game.players[index].entity = Some(entity);
+    }
+
+    // the server's creatures, only their positions are sent
+    while game.enemies.len() < latest.enemies.len() {
+        game.enemies.push(Enemy::new(Cell::new(0, 0), Behaviour::Wander));
+        let index = game.enemies.len() - 1;
+        let entity = spawn_enemy_entity(&mut commands, &asset_server, &game, index);
+        game.enemies[index].entity = Some(entity);
+    }
+
+    let game = &mut *game;
+    game.elapsed = latest.elapsed;
+
+    for (index, enemy) in game.enemies.iter_mut().enumerate() {
+        let (Some(start), Some(end)) = (from.enemies.get(index), to.enemies.get(index)) else {
+            continue;
+        };
+
+        enemy.i = start.i + (end.i - start.i) * t;
+        enemy.j = start.j + (end.j - start.j) * t;
+        enemy.rotation = end.rotation;
+
+        if let Some(mut transform) = enemy.entity.and_then(|entity| transforms.get_mut(entity).ok()) {
+            transform.translation =
+                Vec3::new(enemy.i, game.board.height_at(enemy.position()), enemy.j);
+            transform.rotation = Quat::from_rotation_y(start.rotation)
+                .slerp(Quat::from_rotation_y(end.rotation), t);
+        }
+    }
+
+    for (index, player) in game.players.iter_mut().enumerate() {
+        if let Some(state) = latest.players.get(index) {
+            player.score = state.score;
+            player.cake_eaten = state.cake_eaten;
+            player.effects = state.effects.clone();
+        }
+
+        let (Some(start), Some(end)) = (from.players.get(index), to.players.get(index)) else {
+            continue;
+        };
+
+        player.i = start.i + (end.i - start.i) * t;
+        player.j = start.j + (end.j - start.j) * t;
+        player.rotation = end.rotation;
+
+        if let Some(mut transform) = player.entity.and_then(|entity| transforms.get_mut(entity).ok()) {
+            let position = Vec3::new(player.i, 0.0, player.j);
+            *transform = Transform {
+                translation: Vec3::new(player.i, game.board.height_at(position), player.j),
+                rotation: Quat::from_rotation_y(start.rotation)
+                    .slerp(Quat::from_rotation_y(end.rotation), t),
+                ..default()
+            };
+        }
+    }
+
+    // rebuild the pickups whenever one comes or goes, otherwise move them between snapshots
+    let kinds_on_board = |pickups: &[(usize, f32, f32)]| -> Vec<usize> {
+        pickups.iter().map(|&(kind, _, _)| kind).collect()
+    };
+    let current: Vec<usize> = game.pickups.iter().map(|pickup| pickup.kind).collect();
+    if current != kinds_on_board(&latest.pickups) {
+        for pickup in game.pickups.drain(..) {
+            if let Some(entity) = pickup.entity {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+
+        for &(kind, i, j) in &latest.pickups {
+            game.pickups.push(Pickup::at(kind, i, j));
+            let index = game.pickups.len() - 1;
+            game.pickups[index].entity =
+                Some(spawn_pickup_entity(&mut commands, &asset_server, game, &kinds, index));
+        }
+    }
+
+    for (index, pickup) in game.pickups.iter_mut().enumerate() {
+        let (Some(&(from_kind, start_i, start_j)), Some(&(to_kind, end_i, end_j))) =
+            (from.pickups.get(index), to.pickups.get(index))
+        else {
+            continue;
+        };
+        // one came or went between the two snapshots, it stays where the latest one says
+        if from_kind != pickup.kind || to_kind != pickup.kind {
+            continue;
+        }
+
+        pickup.i = start_i + (end_i - start_i) * t;
+        pickup.j = start_j + (end_j - start_j) * t;
+
+        if let Some(mut transform) = pickup.entity.and_then(|entity| transforms.get_mut(entity).ok()) {
+            transform.translation = Vec3::new(
+                pickup.i,
+                game.board.height_at(pickup.position()) + PICKUP_LIFT,
+                pickup.j,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pickup::Effect;
+    use crate::test_support::*;
+
+    #[test]
+    fn snapshots_fit_in_a_packet() {
+        let effect = ActiveEffect {
+            name: "a rather long effect name ".repeat(4),
+            effect: Effect::Shield,
+            remaining: Duration::from_secs(5),
+        };
+        let mut game = Game {
+            players: (0..8)
+                .map(|_| Player {
+                    effects: vec![effect.clone(); 3],
+                    ..Player::new(1.0, 1.0, None)
+                })
+                .collect(),
+            ..default()
+        };
+
+        // too big with the effects, so they are left out
+        let bytes = encode_snapshot(Snapshot::from_game(1, &game)).unwrap();
+        assert!(bytes.len() <= MAX_PACKET_SIZE);
+        let Ok(ServerMessage::Snapshot(snapshot)) = bincode::deserialize(&bytes) else {
+            panic!("not a snapshot");
+        };
+        assert_eq!(snapshot.players.len(), 8);
+        assert!(snapshot.players.iter().all(|player| player.effects.is_empty()));
+
+        // a full game on hard still fits, even with a few more pickups than usual
+        game.players = (0..MAX_PLAYERS)
+            .map(|_| Player {
+                effects: vec![effect.clone(); 3],
+                ..Player::new(1.0, 1.0, None)
+            })
+            .collect();
+        game.enemies = (0..3)
+            .map(|_| Enemy::new(Cell::new(0, 0), Behaviour::Wander))
+            .collect();
+        game.pickups = (0..8).map(|kind| Pickup::at(kind, 1.0, 1.0)).collect();
+        assert!(encode_snapshot(Snapshot::from_game(2, &game)).is_ok());
+
+        // far too many aliens to fit at all
+        game.players = (0..200).map(|_| Player::new(1.0, 1.0, None)).collect();
+        assert!(encode_snapshot(Snapshot::from_game(3, &game)).is_err());
+    }
+
+    // a server on a free loopback port, as `--server` runs it
+    fn server_app() -> (App, SocketAddr) {
+        let server = NetServer::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+        let addr = server.socket.local_addr().unwrap();
+
+        let mut app = test_app_without_startup(0);
+        app.insert_resource(server)
+            .add_systems(PreUpdate, receive_client_messages)
+            .add_systems(Update, send_snapshots);
+        app.update();
+        (app, addr)
+    }
+
+    // keeps the server running while a client connects from another thread
+    fn connect(server: &mut App, addr: SocketAddr) -> io::Result<NetClient> {
+        let connecting = std::thread::spawn(move || NetClient::connect(addr));
+        while !connecting.is_finished() {
+            server.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        connecting.join().unwrap().map(|(client, _, _)| client)
+    }
+
+    #[test]
+    fn clients_join_and_play_over_loopback() {
+        let (mut server, addr) = server_app();
+        let client = connect(&mut server, addr).unwrap();
+        assert_eq!(client.player, 0);
+        let start = player_position(&server, 0);
+
+        let walk = PlayerInput {
+            up: true,
+            ..default()
+        };
+        let input = ClientMessage::Input {
+            sequence: 1,
+            input: walk,
+        };
+        client.socket.send(&encode(&input).unwrap()).unwrap();
+
+        // until a snapshot shows the alien walking
+        let mut buffer = [0; MAX_PACKET_SIZE];
+        let mut latest = None;
+        for _ in 0..500 {
+            server.update();
+            std::thread::sleep(Duration::from_millis(1));
+            while let Ok(size) = client.socket.recv(&mut buffer) {
+                if let Ok(ServerMessage::Snapshot(snapshot)) = bincode::deserialize(&buffer[..size])
+                {
+                    latest = Some(snapshot);
+                }
+            }
+            if latest.as_ref().is_some_and(|snapshot: &Snapshot| snapshot.players[0].i > start.0) {
+                break;
+            }
+        }
+
+        let snapshot = latest.expect("no snapshot from the server");
+        assert_eq!(snapshot.players.len(), 1);
+        assert!(snapshot.players[0].i > start.0, "alien at {}", snapshot.players[0].i);
+        assert_eq!(server.world.resource::<Game>().players[0].input, walk);
+    }
+
+    #[test]
+    fn full_servers_turn_clients_away() {
+        let (mut server, addr) = server_app();
+        server.world.resource_mut::<Game>().players =
+            (0..MAX_PLAYERS).map(|_| Player::new(1.0, 1.0, None)).collect();
+        let mut net_server = server.world.resource_mut::<NetServer>();
+        for player in 0..MAX_PLAYERS {
+            // clients that never time out
+            let client = RemoteClient {
+                player,
+                last_sequence: 0,
+                last_heard: f32::INFINITY,
+            };
+            let client_addr = (Ipv4Addr::LOCALHOST, 40000 + player as u16).into();
+            net_server.clients.insert(client_addr, client);
+        }
+
+        let Err(error) = connect(&mut server, addr) else {
+            panic!("joined a full game");
+        };
+        assert!(error.to_string().contains("the game is full"), "{error}");
+        assert_eq!(server.world.resource::<Game>().players.len(), MAX_PLAYERS);
+    }
+}
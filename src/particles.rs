@@ -0,0 +1,384 @@
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetPersistencePolicy;
+use rand::Rng;
+
+use crate::camera_tracking::TrackingCamera;
+use crate::pickup::CakeEaten;
+
+/// Most particles alive at once, emitters skip particles past this
+pub const MAX_PARTICLES: usize = 512;
+
+/// A square facing +Z, centered on the origin
+pub struct Billboard {
+    pub size: f32,
+}
+
+impl From<Billboard> for Mesh {
+    fn from(b: Billboard) -> Self {
+        let half = b.size / 2.0;
+
+        let positions = vec![
+            [-half, -half, 0.0],
+            [half, -half, 0.0],
+            [half, half, 0.0],
+            [-half, half, 0.0],
+        ];
+        let normals = vec![[0.0, 0.0, 1.0]; 4];
+        let uvs = vec![[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+        let indices = vec![0, 1, 2, 0, 2, 3];
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetPersistencePolicy::Unload);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh
+    }
+}
+
+/// The looks and motion of a particle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ParticleKind {
+    /// Thrown out when a cake is eaten
+    Crumbs,
+    /// Kicked up by walking aliens
+    Dust,
+    /// Drifting up around the cake
+    Sparkle,
+}
+
+impl ParticleKind {
+    pub const ALL: [ParticleKind; 3] =
+        [ParticleKind::Crumbs, ParticleKind::Dust, ParticleKind::Sparkle];
+
+    fn color(self) -> Color {
+        match self {
+            ParticleKind::Crumbs => Color::rgba(1.0, 0.8, 0.5, 0.9),
+            ParticleKind::Dust => Color::rgba(0.6, 0.55, 0.45, 0.5),
+            ParticleKind::Sparkle => Color::rgba(1.0, 1.0, 0.4, 0.9),
+        }
+    }
+
+    // seconds a particle lives
+    fn lifetime(self) -> f32 {
+        match self {
+            ParticleKind::Crumbs => 0.8,
+            ParticleKind::Dust => 0.5,
+            ParticleKind::Sparkle => 1.0,
+        }
+    }
+
+    // starting size, particles shrink to nothing over their lifetime
+    fn size(self) -> f32 {
+        match self {
+            ParticleKind::Crumbs => 0.12,
+            ParticleKind::Dust => 0.25,
+            ParticleKind::Sparkle => 0.08,
+        }
+    }
+
+    // where a new particle starts, around the emitter, and how it sets off
+    fn launch(self, rng: &mut impl Rng) -> (Vec3, Vec3) {
+        let mut around = |radius: f32| {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            Vec3::new(angle.cos(), 0.0, angle.sin()) * radius
+        };
+
+        match self {
+            ParticleKind::Crumbs => {
+                let out = around(1.0);
+                (Vec3::Y * 0.3, out * 2.0 + Vec3::Y * 3.0)
+            }
+            ParticleKind::Dust => (around(0.2) + Vec3::Y * 0.05, around(0.3) + Vec3::Y * 0.4),
+            ParticleKind::Sparkle => (around(0.5) + Vec3::Y * 0.3, Vec3::Y * 0.6),
+        }
+    }
+
+    // pulls the particle down, sparkles float
+    fn gravity(self) -> f32 {
+        match self {
+            ParticleKind::Crumbs => 9.0,
+            ParticleKind::Dust => 0.5,
+            ParticleKind::Sparkle => 0.0,
+        }
+    }
+}
+
+/// Emits particles from the entity's position
+#[derive(Component, Clone, Debug)]
+pub struct ParticleEmitter {
+    pub kind: ParticleKind,
+    /// Particles per second
+    pub rate: f32,
+    /// Only emit while the entity moves
+    pub when_moving: bool,
+    /// Particles to emit at once, after which the emitter's entity is despawned
+    pub burst: Option<usize>,
+    // particles owed from earlier frames
+    pending: f32,
+    last_position: Option<Vec3>,
+}
+
+impl ParticleEmitter {
+    pub fn new(kind: ParticleKind, rate: f32) -> Self {
+        ParticleEmitter {
+            kind,
+            rate,
+            when_moving: false,
+            burst: None,
+            pending: 0.0,
+            last_position: None,
+        }
+    }
+
+    pub fn when_moving(mut self) -> Self {
+        self.when_moving = true;
+        self
+    }
+
+    pub fn burst(kind: ParticleKind, count: usize) -> Self {
+        ParticleEmitter {
+            burst: Some(count),
+            ..ParticleEmitter::new(kind, 0.0)
+        }
+    }
+}
+
+/// A pooled particle, hidden while it is not in use
+#[derive(Component)]
+pub struct Particle {
+    kind: ParticleKind,
+    velocity: Vec3,
+    age: f32,
+}
+
+/// Particles waiting to be reused, and what new ones are made of
+#[derive(Resource)]
+pub struct ParticlePool {
+    pub free: Vec<Entity>,
+    /// Particles spawned so far, free or in use
+    pub spawned: usize,
+    mesh: Handle<Mesh>,
+    materials: Vec<(ParticleKind, Handle<StandardMaterial>)>,
+}
+
+impl ParticlePool {
+    fn material(&self, kind: ParticleKind) -> Handle<StandardMaterial> {
+        self.materials
+            .iter()
+            .find(|(material_kind, _)| *material_kind == kind)
+            .map(|(_, material)| material.clone())
+            .unwrap_or_default()
+    }
+}
+
+pub fn setup_particles(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let materials = ParticleKind::ALL
+        .iter()
+        .map(|kind| {
+            let material = materials.add(StandardMaterial {
+                base_color: kind.color(),
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                double_sided: true,
+                cull_mode: None,
+                ..default()
+            });
+            (*kind, material)
+        })
+        .collect();
+
+    commands.insert_resource(ParticlePool {
+        free: Vec::new(),
+        spawned: 0,
+        mesh: meshes.add(Mesh::from(Billboard { size: 1.0 })),
+        materials,
+    });
+}
+
+// crumbs fly from wherever a cake is eaten
+pub fn burst_eaten_cakes(mut commands: Commands, mut eaten: EventReader<CakeEaten>) {
+    for cake in eaten.read() {
+        // placed straight away, transforms only propagate at the end of the frame
+        let transform = Transform::from_translation(cake.position);
+        commands.spawn((
+            ParticleEmitter::burst(ParticleKind::Crumbs, 24),
+            TransformBundle {
+                local: transform,
+                global: transform.into(),
+            },
+        ));
+    }
+}
+
+pub fn emit_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    mut emitters: Query<(Entity, &mut ParticleEmitter, &GlobalTransform)>,
+    mut particles: Query<(
+        &mut Particle,
+        &mut Transform,
+        &mut Visibility,
+        &mut Handle<StandardMaterial>,
+    )>,
+) {
+    let mut rng = rand::thread_rng();
+
+    for (entity, mut emitter, transform) in emitters.iter_mut() {
+        let position = transform.translation();
+        let moved = emitter
+            .last_position
+            .is_some_and(|last| last.distance(position) > 0.001);
+        emitter.last_position = Some(position);
+
+        let count = match emitter.burst {
+            Some(count) => {
+                commands.entity(entity).despawn_recursive();
+                count
+            }
+            None if emitter.when_moving && !moved => {
+                emitter.pending = 0.0;
+                0
+            }
+            None => {
+                emitter.pending += emitter.rate * time.delta_seconds();
+                let count = emitter.pending as usize;
+                emitter.pending -= count as f32;
+                count
+            }
+        };
+
+        let kind = emitter.kind;
+        for _ in 0..count {
+            let (offset, velocity) = kind.launch(&mut rng);
+            let particle = Particle {
+                kind,
+                velocity,
+                age: 0.0,
+            };
+            let particle_transform =
+                Transform::from_translation(position + offset).with_scale(Vec3::splat(kind.size()));
+
+            // reuse a free particle, or make a new one while under the limit
+            if let Some(free) = pool.free.pop() {
+                if let Ok((mut old, mut old_transform, mut visibility, mut material)) =
+                    particles.get_mut(free)
+                {
+                    *old = particle;
+                    *old_transform = particle_transform;
+                    *visibility = Visibility::Visible;
+                    *material = pool.material(kind);
+                }
+            } else if pool.spawned < MAX_PARTICLES {
+                pool.spawned += 1;
+                commands.spawn((
+                    PbrBundle {
+                        mesh: pool.mesh.clone(),
+                        material: pool.material(kind),
+                        transform: particle_transform,
+                        ..default()
+                    },
+                    NotShadowCaster,
+                    particle,
+                ));
+            }
+        }
+    }
+}
+
+// move, shrink and turn the particles to the first player's view, finished ones go back to
+// the pool
+pub fn update_particles(
+    time: Res<Time>,
+    mut pool: ResMut<ParticlePool>,
+    cameras: Query<(&TrackingCamera, &GlobalTransform)>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform, &mut Visibility)>,
+) {
+    let facing = cameras
+        .iter()
+        .min_by_key(|(camera, _)| camera.player)
+        .map(|(_, transform)| transform.compute_transform().rotation)
+        .unwrap_or_default();
+    let dt = time.delta_seconds();
+
+    for (entity, mut particle, mut transform, mut visibility) in particles.iter_mut() {
+        if *visibility == Visibility::Hidden {
+            continue;
+        }
+
+        particle.age += dt;
+        let lifetime = particle.kind.lifetime();
+        if particle.age >= lifetime {
+            *visibility = Visibility::Hidden;
+            pool.free.push(entity);
+            continue;
+        }
+
+        particle.velocity.y -= particle.kind.gravity() * dt;
+        transform.translation += particle.velocity * dt;
+        transform.rotation = facing;
+        transform.scale = Vec3::splat(particle.kind.size() * (1.0 - particle.age / lifetime));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn particles_are_pooled() {
+        let mut app = test_app(1);
+        app.init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .add_systems(
+                Update,
+                (
+                    burst_eaten_cakes,
+                    emit_particles.after(burst_eaten_cakes),
+                    update_particles.after(emit_particles),
+                ),
+            );
+        app.world.run_system_once(setup_particles);
+
+        // only the bursts, not the sparkle around the cake
+        let emitters: Vec<Entity> = app
+            .world
+            .query_filtered::<Entity, With<ParticleEmitter>>()
+            .iter(&app.world)
+            .collect();
+        for entity in emitters {
+            app.world.entity_mut(entity).remove::<ParticleEmitter>();
+        }
+
+        let burst = |app: &mut App| {
+            app.world.send_event(CakeEaten {
+                position: Vec3::ZERO,
+            });
+            run_frames(app, 2);
+        };
+        let mut particles = app.world.query::<(&Particle, &Visibility)>();
+
+        burst(&mut app);
+        let crumbs = particles.iter(&app.world).count();
+        assert_eq!(crumbs, 24);
+
+        // they all finish and the next burst reuses them
+        run_frames(&mut app, 120);
+        assert!(particles
+            .iter(&app.world)
+            .all(|(_, visibility)| *visibility == Visibility::Hidden));
+        burst(&mut app);
+        assert_eq!(particles.iter(&app.world).count(), crumbs);
+        assert_eq!(app.world.resource::<ParticlePool>().spawned, crumbs);
+    }
+}
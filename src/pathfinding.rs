@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Mutex;
+
+use crate::board::{Board, Cell};
+
+/// Highest step up or down between neighbouring tiles that can be walked
+pub const MAX_STEP_HEIGHT: f32 = 0.5;
+
+// extra cost per unit of height climbed or descended, so flat routes are preferred
+const HEIGHT_COST: f32 = 2.0;
+
+// paths kept per board before the cache starts over
+const CACHE_LIMIT: usize = 1024;
+
+type Paths = HashMap<(Cell, Cell), Option<Vec<Cell>>>;
+
+/// Paths already found on a board, tiles never change once a board is built so they stay
+/// valid for as long as the board lives
+#[derive(Debug, Default)]
+pub struct PathCache(Mutex<Paths>);
+
+// a copied board finds its own paths
+impl Clone for PathCache {
+    fn clone(&self) -> Self {
+        PathCache::default()
+    }
+}
+
+// open set entry, ordered so the heap pops the lowest estimated total cost first
+struct Candidate {
+    estimate: f32,
+    cell: Cell,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl Board {
+    /// Cost of stepping between two neighbouring cells, `None` when it can't be walked
+    pub fn step_cost(&self, from: Cell, to: Cell) -> Option<f32> {
+        let (from_tile, to_tile) = (self.get(from)?, self.get(to)?);
+        if !to_tile.walkable {
+            return None;
+        }
+
+        let climb = (to_tile.height - from_tile.height).abs();
+        (climb <= MAX_STEP_HEIGHT).then_some(1.0 + climb * HEIGHT_COST)
+    }
+
+    /// Shortest walkable route from `from` to `to` along i and j, both ends included.
+    /// Results are cached on the board.
+    pub fn find_path(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        let mut cache = self.path_cache().0.lock().unwrap();
+
+        if let Some(path) = cache.get(&(from, to)) {
+            return path.clone();
+        }
+
+        let path = self.search(from, to);
+
+        if cache.len() >= CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert((from, to), path.clone());
+
+        path
+    }
+
+    // A* with the manhattan distance, which never overestimates as every step costs at least 1
+    fn search(&self, from: Cell, to: Cell) -> Option<Vec<Cell>> {
+        if !self.get(from)?.walkable || !self.get(to)?.walkable {
+            return None;
+        }
+
+        let distance = |cell: Cell| (cell.i.abs_diff(to.i) + cell.j.abs_diff(to.j)) as f32;
+
+        let mut open = BinaryHeap::from([Candidate {
+            estimate: distance(from),
+            cell: from,
+        }]);
+        let mut cost = HashMap::from([(from, 0.0)]);
+        let mut came_from = HashMap::new();
+
+        while let Some(Candidate { estimate, cell }) = open.pop() {
+            if cell == to {
+                let mut path = vec![to];
+                while let Some(previous) = came_from.get(path.last().unwrap()) {
+                    path.push(*previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let cell_cost = cost[&cell];
+
+            // a cheaper way here was already handled
+            if estimate > cell_cost + distance(cell) {
+                continue;
+            }
+
+            for neighbour in self.neighbours(cell) {
+                let Some(step) = self.step_cost(cell, neighbour) else {
+                    continue;
+                };
+
+                let neighbour_cost = cell_cost + step;
+                if neighbour_cost < cost.get(&neighbour).copied().unwrap_or(f32::INFINITY) {
+                    cost.insert(neighbour, neighbour_cost);
+                    came_from.insert(neighbour, cell);
+                    open.push(Candidate {
+                        estimate: neighbour_cost + distance(neighbour),
+                        cell: neighbour,
+                    });
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn paths_are_shortest_and_walkable() {
+        let board = walled_board();
+
+        let path = board.find_path(Cell::new(0, 0), Cell::new(3, 0)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.first(), Some(&Cell::new(0, 0)));
+        assert_eq!(path.last(), Some(&Cell::new(3, 0)));
+
+        // through the gap in the wall
+        let path = board.find_path(Cell::new(0, 0), Cell::new(0, 3)).unwrap();
+        assert_eq!(path.len(), 4 + 3 + 4 + 1);
+        assert!(path.contains(&Cell::new(4, 2)));
+        for pair in path.windows(2) {
+            assert_eq!(pair[0].i.abs_diff(pair[1].i) + pair[0].j.abs_diff(pair[1].j), 1);
+            assert!(board.get(pair[1]).unwrap().walkable);
+        }
+
+        assert_eq!(board.find_path(Cell::new(1, 1), Cell::new(1, 1)), Some(vec![Cell::new(1, 1)]));
+    }
+
+    #[test]
+    fn paths_avoid_walls_cliffs_and_the_edge() {
+        let board = walled_board();
+
+        assert_eq!(board.find_path(Cell::new(0, 0), Cell::new(1, 2)), None);
+        assert_eq!(board.find_path(Cell::new(0, 3), Cell::new(0, 4)), None);
+        assert_eq!(board.find_path(Cell::new(0, 0), Cell::new(5, 0)), None);
+
+        // asking again comes from the cache and gives the same answer
+        let path = board.find_path(Cell::new(4, 4), Cell::new(0, 0));
+        assert!(path.is_some());
+        assert_eq!(board.find_path(Cell::new(4, 4), Cell::new(0, 0)), path);
+    }
+}
@@ -0,0 +1,197 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::board::{Board, Cell};
+use crate::camera_tracking::TrackingCamera;
+use crate::game_state::*;
+
+/// Where the mouse is in the primary window, in logical pixels. Replays set it from the
+/// recording so clicks land on the same tiles.
+#[derive(Resource, Default)]
+pub struct CursorPosition(pub Option<Vec2>);
+
+/// The board tile under the mouse
+#[derive(Resource, Default)]
+pub struct HoveredCell(pub Option<Cell>);
+
+pub fn track_cursor(
+    primary_query: Query<&Window, With<PrimaryWindow>>,
+    mut cursor: ResMut<CursorPosition>,
+) {
+    cursor.0 = primary_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+}
+
+/// The tile a ray from the camera hits first. The ray is followed in short steps from the
+/// height of the highest tile down to just below the lowest, so raised tiles hide the ones
+/// behind them.
+pub fn ray_to_cell(board: &Board, origin: Vec3, direction: Vec3) -> Option<Cell> {
+    // looking level or up never reaches the ground
+    if direction.y > -f32::EPSILON {
+        return None;
+    }
+
+    let mut heights = board
+        .cells()
+        .filter_map(|cell| board.get(cell))
+        .map(|tile| tile.height);
+    let first = heights.next()?;
+    let (bottom, top) = heights.fold((first, first), |(bottom, top), height| {
+        (bottom.min(height), top.max(height))
+    });
+    let distance_to = |height: f32| (height - origin.y) / direction.y;
+    let (start, end) = (distance_to(top).max(0.0), distance_to(bottom - 0.01));
+    if end < 0.0 {
+        return None;
+    }
+
+    // a tenth of a tile at a time, off the board the ray goes on
+    let steps = (((end - start) * direction.length() * 10.0).ceil() as usize).max(1);
+    (0..=steps)
+        .map(|step| origin + direction * (start + (end - start) * step as f32 / steps as f32))
+        .find_map(|position| {
+            let cell = board.world_to_cell(position)?;
+            let tile = board.get(cell)?;
+            (position.y <= tile.height).then_some(cell)
+        })
+}
+
+/// Send a player along the shortest path to `target`, false when it can't be reached
+pub fn walk_to(game: &mut Game, index: usize, target: Cell) -> bool {
+    let Some(player) = game.players.get(index) else {
+        return false;
+    };
+    let Some(start) = game.board.world_to_cell(Vec3::new(player.i, 0.0, player.j)) else {
+        return false;
+    };
+
+    match game.board.find_path(start, target) {
+        Some(path) => {
+            game.players[index].path = path;
+            true
+        }
+        None => false,
+    }
+}
+
+// find the tile under the mouse in whichever player's view it is, a left click walks that
+// player's alien there
+pub fn pick_cell(
+    cursor: Res<CursorPosition>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    cameras: Query<(&Camera, &GlobalTransform, &TrackingCamera)>,
+    mut hovered: ResMut<HoveredCell>,
+    mut game: ResMut<Game>,
+) {
+    hovered.0 = None;
+
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+
+    for (camera, camera_transform, tracking_camera) in cameras.iter() {
+        let Some(viewport) = camera.logical_viewport_rect() else {
+            continue;
+        };
+        if !viewport.contains(cursor) {
+            continue;
+        }
+
+        let Some(ray) = camera.viewport_to_world(camera_transform, cursor - viewport.min) else {
+            continue;
+        };
+        let Some(cell) = ray_to_cell(&game.board, ray.origin, Vec3::from(ray.direction)) else {
+            continue;
+        };
+
+        hovered.0 = Some(cell);
+
+        if mouse_buttons.just_pressed(MouseButton::Left)
+            && !walk_to(&mut game, tracking_camera.player, cell)
+        {
+            info!("No path to {cell:?}");
+        }
+        return;
+    }
+}
+
+pub fn draw_hovered_cell(hovered: Res<HoveredCell>, game: Res<Game>, mut gizmos: Gizmos) {
+    let Some(cell) = hovered.0 else {
+        return;
+    };
+
+    // lying flat just above the tile
+    gizmos.rect(
+        game.board.cell_to_world(cell) + Vec3::Y * 0.02,
+        Quat::from_rotation_x(PI / 2.),
+        Vec2::ONE,
+        Color::WHITE,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Tile;
+    use crate::test_support::*;
+
+    #[test]
+    fn clicks_pick_the_tile_under_the_cursor() {
+        let board = walled_board();
+
+        // straight down and at an angle onto the raised corner
+        assert_eq!(
+            ray_to_cell(&board, Vec3::new(3.2, 10.0, 1.9), Vec3::NEG_Y),
+            Some(Cell::new(3, 2))
+        );
+        let direction = Vec3::new(0.0, -1.0, 1.0).normalize();
+        assert_eq!(
+            ray_to_cell(&board, Vec3::new(0.0, 5.0, -1.0), direction),
+            Some(Cell::new(0, 4))
+        );
+
+        // off the board, or looking up
+        assert_eq!(ray_to_cell(&board, Vec3::new(-3.0, 10.0, 1.0), Vec3::NEG_Y), None);
+        assert_eq!(ray_to_cell(&board, Vec3::new(1.0, 10.0, 1.0), Vec3::Y), None);
+    }
+
+    #[test]
+    fn clicks_reach_tiles_below_the_ground() {
+        // spawn_game_board sinks tiles down to -0.1
+        let board = Board::new(BoardSize::default(), |cell| {
+            Tile::new(if cell == Cell::new(2, 2) { -0.1 } else { 0.1 })
+        });
+
+        assert_eq!(
+            ray_to_cell(&board, Vec3::new(2.0, 10.0, 2.0), Vec3::NEG_Y),
+            Some(Cell::new(2, 2))
+        );
+        let direction = Vec3::new(0.0, -1.0, 1.0).normalize();
+        assert_eq!(
+            ray_to_cell(&board, Vec3::new(2.0, 5.0, -2.9), direction),
+            Some(Cell::new(2, 2))
+        );
+    }
+
+    #[test]
+    fn clicked_aliens_walk_to_the_tile() {
+        let mut app = test_app(1);
+        let target = Cell::new(2, 3);
+
+        assert!(walk_to(&mut app.world.resource_mut::<Game>(), 0, target));
+        run_frames(&mut app, 300);
+
+        assert_eq!(player_position(&app, 0), (2.0, 3.0));
+        assert!(app.world.resource::<Game>().players[0].path.is_empty());
+
+        // the keys take over from a path
+        assert!(walk_to(&mut app.world.resource_mut::<Game>(), 0, Cell::new(2, 10)));
+        press(&mut app, KeyCode::ArrowUp);
+        run_frames(&mut app, 1);
+        assert!(app.world.resource::<Game>().players[0].path.is_empty());
+    }
+}
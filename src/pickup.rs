@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::distributions::WeightedIndex;
+use rand::prelude::Distribution;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{Sound, SoundEffect};
+use crate::board::Cell;
+use crate::game_state::*;
+use crate::save::LoadedSave;
+use crate::scoring::{ScorePopup, ScoreSource};
+use crate::settings::{CakeMovement, Settings};
+use crate::spawn::spawn_pickup_entity;
+
+/// How close an alien has to get to pick something up, in cells
+pub const PICKUP_REACH: f32 = 0.5;
+
+/// How far above the tile surface pickups float
+pub const PICKUP_LIFT: f32 = 0.2;
+
+// attempts at finding a free cell before waiting for the next spawn
+const SPAWN_ATTEMPTS: usize = 10;
+
+/// A cake was eaten at `position` on the board, for the sounds and effects to follow
+#[derive(Event, Clone, Copy, Debug)]
+pub struct CakeEaten {
+    pub position: Vec3,
+}
+
+/// What a pickup does to the alien that collects it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Effect {
+    /// Points and one more cake on the scoreboard
+    Cake { points: i32 },
+    /// Multiplies how fast the alien walks
+    Speed(f32),
+    /// Creatures can't catch the alien
+    Shield,
+    /// Pickups within this many cells are collected
+    Magnet(f32),
+    /// Seconds added to the round
+    TimeBonus(f32),
+}
+
+/// One kind of pickup, as described in `assets/pickups.ron`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PickupKind {
+    pub name: String,
+    pub model: String,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+    /// Colour of the light above it
+    pub light: (f32, f32, f32),
+    pub effect: Effect,
+    /// Seconds the effect lasts, effects without a duration apply once
+    #[serde(default)]
+    pub duration: f32,
+    pub weight: u32,
+    /// Seconds until another one appears after it is collected. Kinds with a respawn time are
+    /// always on the board rather than turning up at random.
+    #[serde(default)]
+    pub respawn: Option<f32>,
+    /// Gets about the board the way the cake movement setting says
+    #[serde(default)]
+    pub moves: bool,
+    /// The light above it comes on as the sun sets, like the aliens' lights
+    #[serde(default)]
+    pub night_light: bool,
+    #[serde(default)]
+    pub sparkles: bool,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+/// Every kind of pickup, read from the data built into the game
+#[derive(Resource, Clone, Debug)]
+pub struct PickupKinds(pub Vec<PickupKind>);
+
+impl PickupKinds {
+    pub fn parse(text: &str) -> Result<PickupKinds, String> {
+        let kinds: Vec<PickupKind> = ron::from_str(text).map_err(|error| error.to_string())?;
+        if kinds.iter().all(|kind| kind.weight == 0) {
+            return Err("no pickup kind has a weight".to_string());
+        }
+
+        let valid = |seconds: f32| seconds.is_finite() && seconds >= 0.0;
+        for kind in &kinds {
+            if !valid(kind.duration) {
+                return Err(format!("{} can't last {} seconds", kind.name, kind.duration));
+            }
+            if let Some(seconds) = kind.respawn.filter(|seconds| !valid(*seconds)) {
+                return Err(format!("{} can't respawn after {seconds} seconds", kind.name));
+            }
+            if let Effect::TimeBonus(seconds) = kind.effect {
+                if !valid(seconds) {
+                    return Err(format!("{} can't add {seconds} seconds to the round", kind.name));
+                }
+            }
+        }
+        Ok(PickupKinds(kinds))
+    }
+}
+
+impl Default for PickupKinds {
+    fn default() -> Self {
+        PickupKinds::parse(include_str!("../assets/pickups.ron"))
+            .unwrap_or_else(|error| panic!("invalid assets/pickups.ron: {error}"))
+    }
+}
+
+/// How many pickups can lie on the board at once, none unless the mode asks for them
+#[derive(Resource, Default)]
+pub struct PickupLimit(pub usize);
+
+#[derive(Resource)]
+pub struct PickupSpawnTimer(pub Timer);
+
+impl Default for PickupSpawnTimer {
+    fn default() -> Self {
+        PickupSpawnTimer(Timer::from_seconds(6.0, TimerMode::Repeating))
+    }
+}
+
+/// Counts down to bringing back each kind with a respawn time while none is on the board,
+/// keyed by index into `PickupKinds`
+#[derive(Resource, Default)]
+pub struct PickupRespawnTimers(pub HashMap<usize, Timer>);
+
+/// Randomness for where pickups appear and how they wander, seeded from the board so replays
+/// and headless runs repeat
+#[derive(Resource)]
+pub struct PickupRng(pub StdRng);
+
+/// A pickup lying on the board
+pub struct Pickup {
+    /// Index into `PickupKinds`
+    pub kind: usize,
+    pub i: f32,
+    pub j: f32,
+    pub entity: Option<Entity>,
+    /// How it gets about the board, kinds that move start out with the cake movement setting
+    pub movement: CakeMovement,
+    /// Cells still to walk for the walking cake movements, the next one first
+    pub path: Vec<Cell>,
+    /// The patrol point being walked to
+    pub waypoint: usize,
+}
+
+impl Pickup {
+    pub fn new(kind: usize, cell: Cell) -> Self {
+        Pickup::at(kind, cell.i as f32, cell.j as f32)
+    }
+
+    /// A pickup anywhere on the board, moving ones aren't always on a cell's center
+    pub fn at(kind: usize, i: f32, j: f32) -> Self {
+        Pickup {
+            kind,
+            i,
+            j,
+            entity: None,
+            movement: CakeMovement::Stationary,
+            path: Vec::new(),
+            waypoint: 0,
+        }
+    }
+
+    /// Where it is on the board, at ground level
+    pub fn position(&self) -> Vec3 {
+        Vec3::new(self.i, 0.0, self.j)
+    }
+}
+
+/// An effect an alien is under and for how much longer
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ActiveEffect {
+    pub name: String,
+    pub effect: Effect,
+    pub remaining: Duration,
+}
+
+impl Player {
+    /// How much faster than normal the alien walks
+    pub fn speed_multiplier(&self) -> f32 {
+        self.effects
+            .iter()
+            .map(|active| match active.effect {
+                Effect::Speed(multiplier) => multiplier,
+                _ => 1.0,
+            })
+            .product()
+    }
+
+    pub fn shielded(&self) -> bool {
+        self.effects
+            .iter()
+            .any(|active| active.effect == Effect::Shield)
+    }
+
+    /// How far away the alien collects pickups
+    pub fn reach(&self) -> f32 {
+        self.effects
+            .iter()
+            .filter_map(|active| match active.effect {
+                Effect::Magnet(radius) => Some(radius),
+                _ => None,
+            })
+            .fold(PICKUP_REACH, f32::max)
+    }
+
+    /// Apply what a pickup does at game time `now`, collecting the same kind again starts its
+    /// time over. Returns the points scored.
+    pub fn collect(&mut self, kind: &PickupKind, now: Duration, time_bonus: &mut Duration) -> i32 {
+        let points = match kind.effect {
+            Effect::Cake { points } => {
+                self.cake_eaten += 1;
+                self.award(ScoreSource::Cake, points, now)
+            }
+            Effect::TimeBonus(seconds) => {
+                *time_bonus += Duration::from_secs_f32(seconds);
+                self.award(ScoreSource::Pickup, 0, now)
+            }
+            // every pickup keeps the combo going
+            _ => self.award(ScoreSource::Pickup, 0, now),
+        };
+
+        if kind.duration > 0.0 {
+            self.effects.retain(|active| active.name != kind.name);
+            self.effects.push(ActiveEffect {
+                name: kind.name.clone(),
+                effect: kind.effect.clone(),
+                remaining: Duration::from_secs_f32(kind.duration),
+            });
+        }
+        points
+    }
+}
+
+// a walkable cell without an alien or a pickup on it, `None` when none turned up in a few tries
+fn free_cell(game: &Game, rng: &mut StdRng) -> Option<Cell> {
+    let size = game.board.size();
+    let on = |position: Vec3, cell: Cell| game.board.world_to_cell(position) == Some(cell);
+
+    (0..SPAWN_ATTEMPTS)
+        .map(|_| Cell::new(rng.gen_range(0..size.width), rng.gen_range(0..size.height)))
+        .find(|cell| {
+            game.board.get(*cell).is_some_and(|tile| tile.walkable)
+                && !game.pickups.iter().any(|pickup| on(pickup.position(), *cell))
+                && !game
+                    .players
+                    .iter()
+                    .any(|player| on(Vec3::new(player.i, 0.0, player.j), *cell))
+        })
+}
+
+/// Put a pickup of `kind` on a free cell and spawn its scene, returns where it went. Kinds that
+/// move get about the board the way `movement` says.
+pub fn place_pickup(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    game: &mut Game,
+    kinds: &PickupKinds,
+    rng: &mut StdRng,
+    kind: usize,
+    movement: CakeMovement,
+) -> Option<Vec3> {
+    let cell = free_cell(game, rng)?;
+    info!("Spawning {} pickup at {cell:?}", kinds.0[kind].name);
+
+    let mut pickup = Pickup::new(kind, cell);
+    if kinds.0[kind].moves {
+        pickup.movement = movement;
+    }
+    game.pickups.push(pickup);
+    let index = game.pickups.len() - 1;
+    game.pickups[index].entity =
+        Some(spawn_pickup_entity(commands, asset_server, game, kinds, index));
+    Some(game.board.cell_to_world(cell))
+}
+
+/// Put one of every kind with a respawn time on the board
+pub fn place_respawning_pickups(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    game: &mut Game,
+    kinds: &PickupKinds,
+    rng: &mut StdRng,
+    movement: CakeMovement,
+) {
+    for (kind, _) in kinds.0.iter().enumerate().filter(|(_, kind)| kind.respawn.is_some()) {
+        place_pickup(commands, asset_server, game, kinds, rng, kind, movement);
+    }
+}
+
+// the pickups from a loaded save get their scenes, otherwise the respawning kinds start out on
+// the board
+pub fn setup_pickups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    board_seed: Res<BoardSeed>,
+    saved: Option<Res<LoadedSave>>,
+) {
+    let mut rng = StdRng::seed_from_u64(board_seed.0);
+
+    if saved.is_some() {
+        for index in 0..game.pickups.len() {
+            game.pickups[index].entity =
+                Some(spawn_pickup_entity(&mut commands, &asset_server, &game, &kinds, index));
+        }
+    } else {
+        place_respawning_pickups(
+            &mut commands,
+            &asset_server,
+            &mut game,
+            &kinds,
+            &mut rng,
+            settings.gameplay.cake_movement,
+        );
+    }
+
+    commands.insert_resource(PickupRng(rng));
+}
+
+// drop a random pickup on a free walkable cell every so often, runs once per gameplay tick
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_pickups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    limit: Res<PickupLimit>,
+    mut rng: ResMut<PickupRng>,
+    mut timer: ResMut<PickupSpawnTimer>,
+    time: Res<Time>,
+    mut sounds: EventWriter<SoundEffect>,
+) {
+    // the respawning kinds don't count towards the limit
+    let random = game
+        .pickups
+        .iter()
+        .filter(|pickup| kinds.0[pickup.kind].respawn.is_none())
+        .count();
+    if !timer.0.tick(time.delta()).finished() || random >= limit.0 {
+        return;
+    }
+
+    let Ok(weights) = WeightedIndex::new(kinds.0.iter().map(|kind| kind.weight)) else {
+        return;
+    };
+    let kind = weights.sample(&mut rng.0);
+
+    let movement = settings.gameplay.cake_movement;
+    if let Some(position) =
+        place_pickup(&mut commands, &asset_server, &mut game, &kinds, &mut rng.0, kind, movement)
+    {
+        sounds.send(SoundEffect {
+            sound: Sound::Spawn,
+            position,
+        });
+    }
+}
+
+// while none of a respawning kind is on the board, wait out its respawn time and put a new one
+// on a free cell
+#[allow(clippy::too_many_arguments)]
+pub fn respawn_pickups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    mut rng: ResMut<PickupRng>,
+    mut timers: ResMut<PickupRespawnTimers>,
+    time: Res<Time>,
+    mut sounds: EventWriter<SoundEffect>,
+) {
+    for (kind, pickup_kind) in kinds.0.iter().enumerate() {
+        let Some(seconds) = pickup_kind.respawn else {
+            continue;
+        };
+        if game.pickups.iter().any(|pickup| pickup.kind == kind) {
+            timers.0.remove(&kind);
+            continue;
+        }
+
+        let timer = timers
+            .0
+            .entry(kind)
+            .or_insert_with(|| Timer::from_seconds(seconds, TimerMode::Once));
+        if !timer.tick(time.delta()).finished() {
+            continue;
+        }
+
+        // tried again next tick when the board is too crowded
+        if let Some(position) = place_pickup(
+            &mut commands,
+            &asset_server,
+            &mut game,
+            &kinds,
+            &mut rng.0,
+            kind,
+            settings.gameplay.cake_movement,
+        ) {
+            timers.0.remove(&kind);
+            sounds.send(SoundEffect {
+                sound: Sound::Spawn,
+                position,
+            });
+        }
+    }
+}
+
+// the aliens collect whatever is within their reach, after they have moved. The first player
+// to reach a pickup gets it.
+pub fn collect_pickups(
+    mut commands: Commands,
+    mut game: ResMut<Game>,
+    kinds: Res<PickupKinds>,
+    mode: Option<Res<GameMode>>,
+    mut popups: EventWriter<ScorePopup>,
+    mut eaten: EventWriter<CakeEaten>,
+) {
+    let game = &mut *game;
+
+    for (index, player) in game.players.iter_mut().enumerate() {
+        let position = Vec3::new(player.i, 0.0, player.j);
+        let reach = player.reach();
+
+        game.pickups.retain(|pickup| {
+            let at = pickup.position();
+            if at.distance(position) > reach {
+                return true;
+            }
+
+            let kind = &kinds.0[pickup.kind];
+            info!("Player {} collected {}", index + 1, kind.name);
+            let points = player.collect(kind, game.elapsed, &mut game.time_bonus);
+            if let Effect::Cake { .. } = kind.effect {
+                eaten.send(CakeEaten {
+                    position: at + Vec3::Y * game.board.height_at(at),
+                });
+                if let Some(mode) = &mode {
+                    game.time_bonus += mode.cake_bonus();
+                }
+            }
+            if points != 0 {
+                popups.send(ScorePopup {
+                    position: at + Vec3::Y * (game.board.height_at(at) + 1.0),
+                    points,
+                });
+            }
+
+            if let Some(entity) = pickup.entity {
+                commands.entity(entity).despawn_recursive();
+            }
+            false
+        });
+    }
+}
+
+// count down the effects the aliens are under
+pub fn expire_effects(mut game: ResMut<Game>, time: Res<Time>) {
+    for player in game.players.iter_mut() {
+        for active in player.effects.iter_mut() {
+            active.remaining = active.remaining.saturating_sub(time.delta());
+        }
+        player.effects.retain(|active| !active.remaining.is_zero());
+    }
+}
+
+/// The effects an alien is under, as shown on the scoreboard
+pub fn effects_text(player: &Player) -> String {
+    player
+        .effects
+        .iter()
+        .map(|active| format!("{} {:.1}s", active.name, active.remaining.as_secs_f32()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::*;
+
+    #[test]
+    fn pickup_kinds_come_from_data() {
+        let kinds = PickupKinds::default();
+        let names: Vec<&str> = kinds.0.iter().map(|kind| kind.name.as_str()).collect();
+        assert_eq!(names, ["cake", "speed", "shield", "magnet", "time"]);
+
+        assert!(PickupKinds::parse("[]").is_err());
+        assert!(PickupKinds::parse("[(name: \"x\")]").is_err());
+    }
+
+    #[test]
+    fn pickup_times_must_be_positive() {
+        let kind = |effect: &str, duration: &str| {
+            format!(
+                "[(name: \"x\", model: \"x.glb\", light: (1.0, 1.0, 1.0), effect: {effect}, \
+                 duration: {duration}, weight: 1)]"
+            )
+        };
+
+        assert!(PickupKinds::parse(&kind("TimeBonus(5.0)", "0.0")).is_ok());
+        assert_eq!(
+            PickupKinds::parse(&kind("Shield", "-1.0")).unwrap_err(),
+            "x can't last -1 seconds"
+        );
+        assert_eq!(
+            PickupKinds::parse(&kind("Shield", "NaN")).unwrap_err(),
+            "x can't last NaN seconds"
+        );
+        assert_eq!(
+            PickupKinds::parse(&kind("TimeBonus(-5.0)", "0.0")).unwrap_err(),
+            "x can't add -5 seconds to the round"
+        );
+    }
+
+    #[test]
+    fn pickups_apply_their_effects() {
+        let kinds = PickupKinds::default();
+        let mut player = Player::new(0.0, 0.0, None);
+        let mut time_bonus = Duration::ZERO;
+
+        for name in ["cake", "speed", "shield", "magnet", "time"] {
+            player.collect(&kinds.0[pickup_kind(&kinds, name)], Duration::ZERO, &mut time_bonus);
+        }
+
+        assert_eq!((player.score, player.cake_eaten), (2, 1));
+        assert_eq!(player.speed_multiplier(), 1.5);
+        assert!(player.shielded());
+        assert_eq!(player.reach(), 3.0);
+        assert_eq!(time_bonus, Duration::from_secs(10));
+        assert_eq!(player.effects.len(), 3);
+
+        // the same kind again starts over instead of stacking
+        player.effects[0].remaining = Duration::from_secs(1);
+        player.collect(&kinds.0[pickup_kind(&kinds, "speed")], Duration::ZERO, &mut time_bonus);
+        assert_eq!(player.effects.len(), 3);
+        assert_eq!(player.speed_multiplier(), 1.5);
+        assert_eq!(player.effects.last().unwrap().remaining, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn aliens_collect_pickups_and_effects_wear_off() {
+        let mut app = test_app(1);
+        let (i, j) = player_position(&app, 0);
+        let speed = pickup_kind(app.world.resource::<PickupKinds>(), "speed");
+        app.world
+            .resource_mut::<Game>()
+            .pickups
+            .push(Pickup::new(speed, Cell::new(i as usize + 1, j as usize)));
+
+        press(&mut app, KeyCode::ArrowUp);
+        run_frames(&mut app, 10);
+        release_all(&mut app);
+
+        let game = app.world.resource::<Game>();
+        assert!(!game.pickups.iter().any(|pickup| pickup.kind == speed));
+        assert_eq!(game.players[0].speed_multiplier(), 1.5);
+
+        run_frames(&mut app, 300);
+        assert!(app.world.resource::<Game>().players[0].effects.is_empty());
+    }
+}
@@ -1,85 +1,127 @@
+use crate::audio::{Sound, SoundEffect, FOOTSTEP_DISTANCE};
+use crate::board::Cell;
 use crate::game_state::*;
+use crate::interpolation::SimulatedTransform;
 use bevy::prelude::*;
 use std::f32::consts::PI;
 
-// control the game character
+/// Board cells the alien walks per second
+pub const PLAYER_SPEED: f32 = 6.0;
+
+// read the keyboard for every locally controlled character
+pub fn read_keyboard_input(keyboard_input: Res<ButtonInput<KeyCode>>, mut game: ResMut<Game>) {
+    for player in game.players.iter_mut() {
+        if let Some(controls) = player.controls {
+            player.input = PlayerInput {
+                up: keyboard_input.pressed(controls.up),
+                down: keyboard_input.pressed(controls.down),
+                left: keyboard_input.pressed(controls.left),
+                right: keyboard_input.pressed(controls.right),
+            };
+        }
+    }
+}
+
+// control the game characters, runs once per gameplay tick
 pub fn move_player(
-    mut commands: Commands,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
     mut game: ResMut<Game>,
-    mut transforms_query: Query<&mut Transform>,
+    mut simulated_query: Query<&mut SimulatedTransform>,
     time: Res<Time>,
+    mut sounds: EventWriter<SoundEffect>,
 ) {
-    let move_step = 0.1;
+    let game = &mut *game;
 
-    if game.player.move_cooldown.tick(time.delta()).finished() {
+    for player in game.players.iter_mut() {
+        let move_step = PLAYER_SPEED * player.speed_multiplier() * time.delta_seconds();
         let mut moved = false;
-        // let mut rotation = 0.0;
+        let mut position = Vec3::new(player.i, 0.0, player.j);
 
-        if keyboard_input.pressed(KeyCode::ArrowUp) {
-            if game.player.i < BOARD_SIZE_I - 1.0 {
-                game.player.i += move_step;
-            }
-            game.player.rotation = -PI / 2.;
+        // the keys take over from a clicked path
+        if player.input.any() {
+            player.path.clear();
+        }
+
+        if player.input.up {
+            position.x += move_step;
+            player.rotation = -PI / 2.;
             moved = true;
         }
-        if keyboard_input.pressed(KeyCode::ArrowDown) {
-            if game.player.i > 0.0 {
-                game.player.i -= move_step;
-            }
-            game.player.rotation = PI / 2.;
+        if player.input.down {
+            position.x -= move_step;
+            player.rotation = PI / 2.;
             moved = true;
         }
-        if keyboard_input.pressed(KeyCode::ArrowRight) {
-            if game.player.j < BOARD_SIZE_J - 1.0 {
-                game.player.j += move_step;
-            }
-            game.player.rotation = -PI;
+        if player.input.right {
+            position.z += move_step;
+            player.rotation = -PI;
             moved = true;
         }
-        if keyboard_input.pressed(KeyCode::ArrowLeft) {
-            if game.player.j > 0.0 {
-                game.player.j -= move_step;
-            }
-            game.player.rotation = 0.0;
+        if player.input.left {
+            position.z -= move_step;
+            player.rotation = 0.0;
             moved = true;
         }
 
+        // walk the path from a click
+        moved |= follow_path(&mut position, &mut player.rotation, &mut player.path, move_step);
+
         // move on the board
         if moved {
-            game.player.move_cooldown.reset();
+            let position = game.board.clamp(position);
+            player.stride += position.distance(Vec3::new(player.i, 0.0, player.j));
+            player.i = position.x;
+            player.j = position.z;
+
+            if player.stride >= FOOTSTEP_DISTANCE {
+                player.stride -= FOOTSTEP_DISTANCE;
+                sounds.send(SoundEffect {
+                    sound: Sound::Footstep,
+                    position: Vec3::new(player.i, game.board.height_at(position), player.j),
+                });
+            }
 
             let new_player_transform = Transform {
-                translation: Vec3::new(
-                    game.player.i,
-                    game.board[game.player.j.round() as usize][game.player.i.round() as usize]
-                        .height,
-                    game.player.j,
-                ),
-                rotation: Quat::from_rotation_y(game.player.rotation),
+                translation: Vec3::new(player.i, game.board.height_at(position), player.j),
+                rotation: Quat::from_rotation_y(player.rotation),
                 ..default()
             };
 
-            // let player_entity = game.player.entity.unwrap();
-            // *transforms_query.get_mut(player_entity).unwrap() = new_player_transform;
-            // let mut player_transform = transforms_query.get_mut(player_entity).unwrap();
-            // *player_transform = new_player_transform;
-
-            if let Some(player_entity) = game.player.entity {
-                if let Ok(mut player_transform) = transforms_query.get_mut(player_entity) {
-                    *player_transform = new_player_transform;
+            if let Some(player_entity) = player.entity {
+                if let Ok(mut simulated) = simulated_query.get_mut(player_entity) {
+                    simulated.current = new_player_transform;
                 }
             }
         }
     }
+}
 
-    // eat the Cake!
-    if let Some(cake_entity) = game.cake.entity {
-        if game.player.i == game.cake.i && game.player.j == game.cake.j {
-            game.score += 2;
-            game.cake_eaten += 1;
-            commands.entity(cake_entity).despawn_recursive();
-            game.cake.entity = None;
-        }
+/// Walk `position` towards the first cell of `path` by at most `step`, along i first and then
+/// j, dropping the cell once it is reached. Returns false when there is nowhere to go.
+pub fn follow_path(position: &mut Vec3, rotation: &mut f32, path: &mut Vec<Cell>, step: f32) -> bool {
+    let Some(next) = path.first() else {
+        return false;
+    };
+    let target = Vec3::new(next.i as f32, position.y, next.j as f32);
+
+    if position.x != target.x {
+        *rotation = if target.x > position.x { -PI / 2. } else { PI / 2. };
+        position.x = step_towards(position.x, target.x, step);
+    } else if position.z != target.z {
+        *rotation = if target.z > position.z { -PI } else { 0.0 };
+        position.z = step_towards(position.z, target.z, step);
+    }
+
+    if *position == target {
+        path.remove(0);
+    }
+    true
+}
+
+// move by at most `step`, landing exactly on `to`
+fn step_towards(from: f32, to: f32, step: f32) -> f32 {
+    if (to - from).abs() <= step {
+        to
+    } else {
+        from + step.copysign(to - from)
     }
 }
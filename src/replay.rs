@@ -0,0 +1,288 @@
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+
+use crate::game_state::BoardSize;
+use crate::picking::CursorPosition;
+use crate::settings::GameplaySettings;
+
+// bump when the replay layout changes, older files are rejected instead of misread
+const REPLAY_VERSION: u32 = 4;
+
+/// The input of one frame and how much time it covered
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ReplayFrame {
+    pub delta: Duration,
+    pub keys: Vec<KeyCode>,
+    pub mouse_buttons: Vec<MouseButton>,
+    pub mouse_motion: Vec2,
+    pub mouse_wheel: f32,
+    pub cursor: Option<Vec2>,
+}
+
+/// A recorded session: the seed the board was built from, the gameplay settings it was played
+/// with and every frame's input
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub version: u32,
+    pub board_seed: u64,
+    pub board_size: BoardSize,
+    pub player_count: usize,
+    /// The difficulty (creatures and cake speed) and cake movement
+    pub gameplay: GameplaySettings,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    pub fn new(
+        board_seed: u64,
+        board_size: BoardSize,
+        player_count: usize,
+        gameplay: GameplaySettings,
+    ) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            board_seed,
+            board_size,
+            player_count,
+            gameplay,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|error| format!("failed to read replay {}: {error}", path.display()))?;
+        let invalid = |error| format!("invalid replay {}: {error}", path.display());
+
+        // the version comes first, check it before reading a layout that may have changed
+        let version: u32 = bincode::deserialize(&bytes).map_err(invalid)?;
+        if version != REPLAY_VERSION {
+            return Err(format!(
+                "replay {} has version {version}, expected {REPLAY_VERSION}",
+                path.display()
+            ));
+        }
+
+        bincode::deserialize(&bytes).map_err(invalid)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let bytes = bincode::serialize(self).map_err(|error| error.to_string())?;
+        std::fs::write(path, bytes)
+            .map_err(|error| format!("failed to write replay {}: {error}", path.display()))
+    }
+}
+
+/// Collects the input of every frame, written to `path` when the app exits
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn new(path: PathBuf, replay: Replay) -> Self {
+        ReplayRecorder { path, replay }
+    }
+}
+
+/// Feeds a recorded session back in place of the real input devices and clock
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    pub frame: usize,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayback { replay, frame: 0 }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.replay.frames.len()
+    }
+}
+
+// runs once the input plugin has updated the button states and queued this frame's events
+pub fn record_input(
+    time: Res<Time>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse_buttons: Res<ButtonInput<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    cursor: Res<CursorPosition>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    recorder.replay.frames.push(ReplayFrame {
+        delta: time.delta(),
+        keys: keys.get_pressed().copied().collect(),
+        mouse_buttons: mouse_buttons.get_pressed().copied().collect(),
+        mouse_motion: mouse_motion.read().map(|motion| motion.delta).sum(),
+        mouse_wheel: mouse_wheel.read().map(|wheel| wheel.y).sum(),
+        cursor: cursor.0,
+    });
+}
+
+pub fn save_recording(mut exit: EventReader<AppExit>, recorder: Res<ReplayRecorder>) {
+    if exit.read().next().is_none() {
+        return;
+    }
+
+    match recorder.replay.save(&recorder.path) {
+        Ok(()) => info!(
+            "Saved replay of {} frames to {}",
+            recorder.replay.frames.len(),
+            recorder.path.display()
+        ),
+        Err(error) => error!("{error}"),
+    }
+}
+
+// advance the clock by the recorded frame time instead of the wall clock
+pub fn playback_frame_time(playback: Res<ReplayPlayback>, mut strategy: ResMut<TimeUpdateStrategy>) {
+    *strategy = if playback.finished() {
+        TimeUpdateStrategy::Automatic
+    } else {
+        TimeUpdateStrategy::ManualDuration(playback.replay.frames[playback.frame].delta)
+    };
+}
+
+// replace whatever the devices reported this frame with the recorded input
+pub fn playback_input(
+    primary_query: Query<Entity, With<PrimaryWindow>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut keys: ResMut<ButtonInput<KeyCode>>,
+    mut mouse_buttons: ResMut<ButtonInput<MouseButton>>,
+    mut mouse_motion: ResMut<Events<MouseMotion>>,
+    mut mouse_wheel: ResMut<Events<MouseWheel>>,
+    mut cursor: ResMut<CursorPosition>,
+) {
+    let Some(frame) = playback.replay.frames.get(playback.frame) else {
+        // hand the devices back on the first frame past the end
+        if playback.frame == playback.replay.frames.len() {
+            info!("Replay finished after {} frames", playback.frame);
+            keys.release_all();
+            mouse_buttons.release_all();
+            playback.frame += 1;
+        }
+        return;
+    };
+
+    set_pressed(&mut keys, &frame.keys);
+    set_pressed(&mut mouse_buttons, &frame.mouse_buttons);
+
+    mouse_motion.clear();
+    if frame.mouse_motion != Vec2::ZERO {
+        mouse_motion.send(MouseMotion {
+            delta: frame.mouse_motion,
+        });
+    }
+
+    mouse_wheel.clear();
+    if frame.mouse_wheel != 0.0 {
+        mouse_wheel.send(MouseWheel {
+            unit: MouseScrollUnit::Line,
+            x: 0.0,
+            y: frame.mouse_wheel,
+            window: primary_query.get_single().unwrap_or(Entity::PLACEHOLDER),
+        });
+    }
+
+    cursor.0 = frame.cursor;
+
+    playback.frame += 1;
+}
+
+// press and release buttons so only `pressed` is held, keeping just_pressed/just_released right
+fn set_pressed<T: Copy + Eq + Hash + Send + Sync + 'static>(
+    input: &mut ButtonInput<T>,
+    pressed: &[T],
+) {
+    let released: Vec<T> = input
+        .get_pressed()
+        .filter(|button| !pressed.contains(button))
+        .copied()
+        .collect();
+
+    for button in released {
+        input.release(button);
+    }
+
+    for button in pressed {
+        input.press(*button);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::TimeSystem;
+
+    use super::*;
+    use crate::player::read_keyboard_input;
+    use crate::settings::{CakeMovement, Difficulty, Settings};
+    use crate::test_support::*;
+
+    #[test]
+    fn replay_reproduces_a_recorded_session() {
+        let path = std::env::temp_dir().join("alien_explorer_test.replay");
+
+        // played with settings other than the ones the playback starts with
+        let gameplay = GameplaySettings {
+            difficulty: Difficulty::Hard,
+            cake_movement: CakeMovement::Patrol,
+        };
+        let mut recording = test_app_without_startup(1);
+        recording.insert_resource(Settings {
+            gameplay: gameplay.clone(),
+            ..default()
+        });
+        recording.update();
+        let replay = Replay::new(1, BoardSize::default(), 1, gameplay);
+        recording
+            .insert_resource(ReplayRecorder::new(path.clone(), replay))
+            .add_systems(PreUpdate, record_input.after(read_keyboard_input));
+
+        press(&mut recording, KeyCode::ArrowUp);
+        run_frames(&mut recording, 20);
+        release_all(&mut recording);
+        press(&mut recording, KeyCode::ArrowLeft);
+        run_frames(&mut recording, 15);
+
+        let recorder = recording.world.resource::<ReplayRecorder>();
+        recorder.replay.save(&path).unwrap();
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(replay, recorder.replay);
+        assert_eq!(replay.frames.len(), 35);
+
+        // as --replay does
+        let mut playback = test_app_without_startup(replay.player_count);
+        playback.insert_resource(Settings {
+            gameplay: replay.gameplay.clone(),
+            ..default()
+        });
+        playback.update();
+        playback
+            .insert_resource(ReplayPlayback::new(replay))
+            .add_systems(First, playback_frame_time.before(TimeSystem))
+            .add_systems(PreUpdate, playback_input.before(read_keyboard_input));
+        run_frames(&mut playback, 35);
+
+        assert!(playback.world.resource::<ReplayPlayback>().finished());
+        assert_eq!(player_position(&playback, 0), player_position(&recording, 0));
+        assert_eq!(cake_position(&playback), cake_position(&recording));
+        assert_eq!(
+            playback.world.resource::<Time>().elapsed(),
+            recording.world.resource::<Time>().elapsed()
+        );
+
+        std::fs::remove_file(path).ok();
+    }
+}
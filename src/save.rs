@@ -0,0 +1,585 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::enemy::{Behaviour, Enemy};
+use crate::game_state::*;
+use crate::level::CurrentLevel;
+use crate::pickup::{ActiveEffect, Pickup, PickupKinds};
+use crate::scoring::Scoring;
+use crate::settings::{CakeMovement, Settings};
+
+/// Version written into new saves. When the layout changes, bump this, keep reading the
+/// old version in `SavedGame::load` and convert it. New fields can also be added with
+/// `#[serde(default)]` so older saves still parse.
+pub const SAVE_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedPlayer {
+    pub i: f32,
+    pub j: f32,
+    pub rotation: f32,
+    pub score: i32,
+    pub cake_eaten: u32,
+    pub effects: Vec<ActiveEffect>,
+    pub scoring: Scoring,
+}
+
+/// A creature and what it was up to, its path is planned again
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedEnemy {
+    pub i: f32,
+    pub j: f32,
+    pub rotation: f32,
+    pub behaviour: Behaviour,
+    pub routine: Behaviour,
+}
+
+/// A pickup on the board, `kind` is its name in `PickupKinds`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedPickup {
+    pub kind: String,
+    pub i: f32,
+    pub j: f32,
+    /// `None` in saves from before each cake kept its own movement, those take the setting
+    pub movement: Option<CakeMovement>,
+}
+
+/// The level being played and the cakes eaten before it started
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct SavedLevel {
+    pub number: usize,
+    pub cakes_at_start: u32,
+}
+
+/// Everything needed to rebuild a game session
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SavedGame {
+    pub version: u32,
+    pub board_seed: u64,
+    /// Tile heights, indexed `[j][i]` like `Game.board`
+    pub board: Vec<Vec<f32>>,
+    pub players: Vec<SavedPlayer>,
+    /// Saves without any get new creatures for the difficulty they are loaded at
+    pub enemies: Vec<SavedEnemy>,
+    /// Everything on the board to pick up, the cake among them unless it has been eaten
+    pub pickups: Vec<SavedPickup>,
+    pub elapsed: Duration,
+    /// Round time added by cakes and pickups
+    pub time_bonus: Duration,
+    pub mode: GameMode,
+    /// `None` when there are no level files, or in saves from before levels were kept
+    pub level: Option<SavedLevel>,
+}
+
+#[derive(Deserialize)]
+struct SavedPlayerV2 {
+    i: f32,
+    j: f32,
+    rotation: f32,
+    score: i32,
+    cake_eaten: u32,
+}
+
+#[derive(Deserialize)]
+struct SavedPickupV2 {
+    kind: String,
+    i: f32,
+    j: f32,
+}
+
+// version 2 kept the players and pickups, but not the creatures, effects, combos or mode
+#[derive(Deserialize)]
+struct SavedGameV2 {
+    board_seed: u64,
+    board: Vec<Vec<f32>>,
+    players: Vec<SavedPlayerV2>,
+    pickups: Vec<SavedPickupV2>,
+    elapsed: Duration,
+}
+
+impl From<SavedGameV2> for SavedGame {
+    fn from(saved: SavedGameV2) -> Self {
+        SavedGame {
+            version: SAVE_VERSION,
+            board_seed: saved.board_seed,
+            board: saved.board,
+            players: saved
+                .players
+                .into_iter()
+                .map(|player| SavedPlayer {
+                    i: player.i,
+                    j: player.j,
+                    rotation: player.rotation,
+                    score: player.score,
+                    cake_eaten: player.cake_eaten,
+                    effects: Vec::new(),
+                    scoring: Scoring::default(),
+                })
+                .collect(),
+            enemies: Vec::new(),
+            pickups: saved
+                .pickups
+                .into_iter()
+                .map(|pickup| SavedPickup {
+                    kind: pickup.kind,
+                    i: pickup.i,
+                    j: pickup.j,
+                    movement: None,
+                })
+                .collect(),
+            elapsed: saved.elapsed,
+            time_bonus: Duration::ZERO,
+            // older saves were always carried on as the first level
+            mode: GameMode::Levels,
+            level: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SavedCakeV1 {
+    i: f32,
+    j: f32,
+}
+
+// version 1 saved only the cake, `None` once it had been eaten
+#[derive(Deserialize)]
+struct SavedGameV1 {
+    board_seed: u64,
+    board: Vec<Vec<f32>>,
+    players: Vec<SavedPlayerV2>,
+    cake: Option<SavedCakeV1>,
+    elapsed: Duration,
+}
+
+impl From<SavedGameV1> for SavedGameV2 {
+    fn from(saved: SavedGameV1) -> Self {
+        SavedGameV2 {
+            board_seed: saved.board_seed,
+            board: saved.board,
+            players: saved.players,
+            pickups: saved
+                .cake
+                .into_iter()
+                .map(|cake| SavedPickupV2 {
+                    kind: "cake".to_string(),
+                    i: cake.i,
+                    j: cake.j,
+                })
+                .collect(),
+            elapsed: saved.elapsed,
+        }
+    }
+}
+
+// only the version is read first, to decide how to parse the rest
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+impl SavedGame {
+    pub fn from_game(
+        game: &Game,
+        kinds: &PickupKinds,
+        board_seed: u64,
+        mode: GameMode,
+        level: Option<&CurrentLevel>,
+    ) -> SavedGame {
+        SavedGame {
+            version: SAVE_VERSION,
+            board_seed,
+            board: game
+                .board
+                .rows()
+                .map(|row| row.iter().map(|tile| tile.height).collect())
+                .collect(),
+            players: game
+                .players
+                .iter()
+                .map(|player| SavedPlayer {
+                    i: player.i,
+                    j: player.j,
+                    rotation: player.rotation,
+                    score: player.score,
+                    cake_eaten: player.cake_eaten,
+                    effects: player.effects.clone(),
+                    scoring: player.scoring.clone(),
+                })
+                .collect(),
+            enemies: game
+                .enemies
+                .iter()
+                .map(|enemy| SavedEnemy {
+                    i: enemy.i,
+                    j: enemy.j,
+                    rotation: enemy.rotation,
+                    behaviour: enemy.behaviour.clone(),
+                    routine: enemy.routine.clone(),
+                })
+                .collect(),
+            pickups: game
+                .pickups
+                .iter()
+                .map(|pickup| SavedPickup {
+                    kind: kinds.0[pickup.kind].name.clone(),
+                    i: pickup.i,
+                    j: pickup.j,
+                    movement: Some(pickup.movement),
+                })
+                .collect(),
+            elapsed: game.elapsed,
+            time_bonus: game.time_bonus,
+            mode,
+            level: level.map(|level| SavedLevel {
+                number: level.number,
+                cakes_at_start: level.cakes_at_start,
+            }),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<SavedGame, String> {
+        let header: SaveHeader = ron::from_str(text).map_err(|error| error.to_string())?;
+
+        let saved: SavedGame = match header.version {
+            1 => SavedGameV2::from(
+                ron::from_str::<SavedGameV1>(text).map_err(|error| error.to_string())?,
+            )
+            .into(),
+            2 => ron::from_str::<SavedGameV2>(text)
+                .map_err(|error| error.to_string())?
+                .into(),
+            3 => ron::from_str(text).map_err(|error| error.to_string())?,
+            version => return Err(format!("unsupported save version {version}")),
+        };
+
+        saved.validate()?;
+        Ok(saved)
+    }
+
+    pub fn load(path: &Path) -> Result<SavedGame, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("failed to read save {}: {error}", path.display()))?;
+        SavedGame::parse(&text).map_err(|error| format!("invalid save {}: {error}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        std::fs::write(path, text)
+            .map_err(|error| format!("failed to write save {}: {error}", path.display()))
+    }
+
+    /// The board is played at the size it was saved with
+    pub fn board_size(&self) -> BoardSize {
+        BoardSize {
+            width: self.board.first().map_or(0, Vec::len),
+            height: self.board.len(),
+        }
+    }
+
+    // the spawn functions index the board with these positions, so reject anything off it
+    fn validate(&self) -> Result<(), String> {
+        let board_size = self.board_size();
+
+        if board_size.clamped() != board_size
+            || self.board.iter().any(|row| row.len() != board_size.width)
+        {
+            return Err("board is not a valid rectangle".to_string());
+        }
+        if self
+            .players
+            .iter()
+            .any(|player| !board_size.contains(player.i, player.j))
+        {
+            return Err("player off the board".to_string());
+        }
+        if self
+            .enemies
+            .iter()
+            .any(|enemy| !board_size.contains(enemy.i, enemy.j))
+        {
+            return Err("creature off the board".to_string());
+        }
+        if self
+            .pickups
+            .iter()
+            .any(|pickup| !board_size.contains(pickup.i, pickup.j))
+        {
+            return Err("pickup off the board".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// A save given with `--load`, the startup systems build the world from it
+#[derive(Resource)]
+pub struct LoadedSave(pub SavedGame);
+
+/// Where F5 writes the current game
+#[derive(Resource)]
+pub struct SaveFile(pub PathBuf);
+
+// replaces the fresh game from setup_game_state with the saved one, before anything spawns
+pub fn restore_saved_game(
+    saved: Res<LoadedSave>,
+    kinds: Res<PickupKinds>,
+    settings: Res<Settings>,
+    mut game: ResMut<Game>,
+) {
+    info!("Restoring saved game");
+
+    let saved = &saved.0;
+
+    for (index, saved_player) in saved.players.iter().enumerate() {
+        if index >= game.players.len() {
+            game.players.push(Player::new(0.0, 0.0, None));
+        }
+
+        let player = &mut game.players[index];
+        player.i = saved_player.i;
+        player.j = saved_player.j;
+        player.rotation = saved_player.rotation;
+        player.score = saved_player.score;
+        player.cake_eaten = saved_player.cake_eaten;
+        player.effects = saved_player.effects.clone();
+        player.scoring = saved_player.scoring.clone();
+    }
+
+    // spawn_enemies spawns their scenes
+    game.enemies = saved
+        .enemies
+        .iter()
+        .map(|saved_enemy| Enemy {
+            entity: None,
+            i: saved_enemy.i,
+            j: saved_enemy.j,
+            rotation: saved_enemy.rotation,
+            behaviour: saved_enemy.behaviour.clone(),
+            routine: saved_enemy.routine.clone(),
+            path: Vec::new(),
+        })
+        .collect();
+
+    // setup_pickups spawns their scenes
+    game.pickups.clear();
+    for saved_pickup in &saved.pickups {
+        let Some(kind) = kinds.0.iter().position(|kind| kind.name == saved_pickup.kind) else {
+            warn!("Skipping saved pickup of unknown kind {}", saved_pickup.kind);
+            continue;
+        };
+        let mut pickup = Pickup::at(kind, saved_pickup.i, saved_pickup.j);
+        if kinds.0[kind].moves {
+            pickup.movement = saved_pickup.movement.unwrap_or(settings.gameplay.cake_movement);
+        }
+        game.pickups.push(pickup);
+    }
+
+    game.elapsed = saved.elapsed;
+    game.time_bonus = saved.time_bonus;
+}
+
+pub fn save_game_on_key(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    game: Res<Game>,
+    kinds: Res<PickupKinds>,
+    board_seed: Res<BoardSeed>,
+    mode: Res<GameMode>,
+    level: Option<Res<CurrentLevel>>,
+    save_file: Res<SaveFile>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F5) {
+        return;
+    }
+
+    let saved = SavedGame::from_game(&game, &kinds, board_seed.0, *mode, level.as_deref());
+    match saved.save(&save_file.0) {
+        Ok(()) => info!("Saved game to {}", save_file.0.display()),
+        Err(error) => error!("{error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::EnemyCount;
+    use crate::level::Level;
+    use crate::pickup::Effect;
+    use crate::test_support::*;
+
+    #[test]
+    fn saved_game_restores_the_session() {
+        let path = std::env::temp_dir().join("alien_explorer_test_save.ron");
+
+        let mut original = test_app(2);
+        press(&mut original, KeyCode::ArrowUp);
+        press(&mut original, KeyCode::KeyD);
+        run_frames(&mut original, 30);
+        original.world.resource_mut::<Game>().players[1].score = 7;
+
+        let saved = SavedGame::from_game(
+            original.world.resource::<Game>(),
+            original.world.resource::<PickupKinds>(),
+            1,
+            GameMode::default(),
+            None,
+        );
+        saved.save(&path).unwrap();
+        let loaded = SavedGame::load(&path).unwrap();
+        assert_eq!(loaded, saved);
+
+        let mut restored = test_app_without_startup(loaded.players.len());
+        restored.insert_resource(LoadedSave(loaded));
+        restored.update();
+
+        let original_game = original.world.resource::<Game>();
+        let restored_game = restored.world.resource::<Game>();
+        let players = original_game.players.iter().zip(&restored_game.players);
+        for (original_player, restored_player) in players {
+            assert_eq!(restored_player.i, original_player.i);
+            assert_eq!(restored_player.j, original_player.j);
+            assert_eq!(restored_player.score, original_player.score);
+        }
+        assert_eq!(restored_game.elapsed, original_game.elapsed);
+        assert!(restored_game.elapsed > std::time::Duration::ZERO);
+        let positions = |game: &Game| -> Vec<(usize, f32, f32)> {
+            game.pickups
+                .iter()
+                .map(|pickup| (pickup.kind, pickup.i, pickup.j))
+                .collect()
+        };
+        assert_eq!(positions(restored_game), positions(original_game));
+        assert!(restored_game.pickups.iter().all(|pickup| pickup.entity.is_some()));
+        assert!(original_game
+            .board
+            .rows()
+            .eq(restored_game.board.rows()));
+
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn version_one_saves_still_load() {
+        let board_size = BoardSize::default();
+        let row = format!("[{}]", vec!["0.0"; board_size.width].join(", "));
+        let board = vec![row; board_size.height].join(", ");
+        let text = format!(
+            "(version: 1, board_seed: 3, board: [{board}], \
+             players: [(i: 2.0, j: 4.0, rotation: 0.0, score: 5, cake_eaten: 2)], \
+             cake: None, elapsed: (secs: 12, nanos: 0))"
+        );
+
+        let saved = SavedGame::parse(&text).unwrap();
+        assert_eq!(saved.players[0].score, 5);
+        assert_eq!(saved.version, SAVE_VERSION);
+        assert!(saved.pickups.is_empty());
+
+        // the cake of a version 1 save becomes a cake pickup
+        let with_cake =
+            SavedGame::parse(&text.replace("cake: None", "cake: Some((i: 3.0, j: 1.0))"));
+        let cake = SavedPickup {
+            kind: "cake".to_string(),
+            i: 3.0,
+            j: 1.0,
+            movement: None,
+        };
+        assert_eq!(with_cake.unwrap().pickups, [cake]);
+        assert_eq!(saved.elapsed.as_secs(), 12);
+
+        assert!(SavedGame::parse(&text.replace("version: 1", "version: 99")).is_err());
+        assert!(SavedGame::parse(&text.replace("i: 2.0", "i: 40.0")).is_err());
+    }
+
+    #[test]
+    fn version_two_saves_still_load() {
+        let board_size = BoardSize::default();
+        let row = format!("[{}]", vec!["0.0"; board_size.width].join(", "));
+        let board = vec![row; board_size.height].join(", ");
+        let text = format!(
+            "(version: 2, board_seed: 3, board: [{board}], \
+             players: [(i: 2.0, j: 4.0, rotation: 0.0, score: 5, cake_eaten: 2)], \
+             pickups: [(kind: \"cake\", i: 3.0, j: 1.0)], elapsed: (secs: 12, nanos: 0))"
+        );
+
+        let saved = SavedGame::parse(&text).unwrap();
+        assert_eq!(saved.version, SAVE_VERSION);
+        assert_eq!(saved.players[0].score, 5);
+        assert_eq!(saved.players[0].scoring, Scoring::default());
+        assert_eq!(saved.pickups[0].movement, None);
+        assert!(saved.enemies.is_empty());
+        assert_eq!((saved.mode, saved.level), (GameMode::Levels, None));
+    }
+
+    #[test]
+    fn saves_keep_the_mode_level_creatures_and_combos() {
+        let mut original = test_app_without_startup(1);
+        original.insert_resource(EnemyCount(2));
+        original.update();
+        {
+            let mut game = original.world.resource_mut::<Game>();
+            game.time_bonus = Duration::from_secs(15);
+            game.players[0].scoring.combo = 3;
+            game.players[0].scoring.multiplier = 2.5;
+            game.players[0].effects.push(ActiveEffect {
+                name: "shield".to_string(),
+                effect: Effect::Shield,
+                remaining: Duration::from_secs(4),
+            });
+            game.pickups[0].movement = CakeMovement::Flee;
+        }
+        run_frames(&mut original, 30);
+
+        let level = CurrentLevel {
+            number: 2,
+            level: Level {
+                name: "test".to_string(),
+                objectives: Vec::new(),
+            },
+            cakes_at_start: 4,
+        };
+        let saved = SavedGame::from_game(
+            original.world.resource::<Game>(),
+            original.world.resource::<PickupKinds>(),
+            1,
+            GameMode::TimeAttack,
+            Some(&level),
+        );
+        let loaded = SavedGame::parse(&ron::to_string(&saved).unwrap()).unwrap();
+        assert_eq!(loaded, saved);
+        assert_eq!(loaded.mode, GameMode::TimeAttack);
+        assert_eq!(
+            loaded.level,
+            Some(SavedLevel {
+                number: 2,
+                cakes_at_start: 4
+            })
+        );
+
+        // loaded with a different number of creatures than it was saved with
+        let mut restored = test_app_without_startup(1);
+        restored
+            .insert_resource(EnemyCount(1))
+            .insert_resource(LoadedSave(loaded));
+        restored.update();
+
+        let original_game = original.world.resource::<Game>();
+        let restored_game = restored.world.resource::<Game>();
+        assert_eq!(restored_game.time_bonus, Duration::from_secs(15));
+        assert_eq!(restored_game.players[0].scoring, original_game.players[0].scoring);
+        assert_eq!(restored_game.players[0].effects, original_game.players[0].effects);
+        assert_eq!(restored_game.pickups[0].movement, CakeMovement::Flee);
+
+        let enemies = |game: &Game| -> Vec<(f32, f32, Behaviour)> {
+            game.enemies
+                .iter()
+                .map(|enemy| (enemy.i, enemy.j, enemy.behaviour.clone()))
+                .collect()
+        };
+        assert_eq!(enemies(restored_game), enemies(original_game));
+        assert!(restored_game.enemies.iter().all(|enemy| enemy.entity.is_some()));
+    }
+}
@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::camera_tracking::TrackingCamera;
+use crate::game_state::*;
+
+/// Pickups closer together than this make a combo
+pub const COMBO_WINDOW: Duration = Duration::from_secs(3);
+
+// each pickup in a combo raises the multiplier this much, up to the maximum
+const MULTIPLIER_STEP: f32 = 0.5;
+const MAX_MULTIPLIER: f32 = 4.0;
+
+// multiplier lost per second, it settles back to 1
+const MULTIPLIER_DECAY: f32 = 0.5;
+
+// how long a popup floats and how far it rises
+const POPUP_TIME: f32 = 1.2;
+const POPUP_RISE: f32 = 1.0;
+
+/// Where a player's points came from, shown on the game over screen
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ScoreBreakdown {
+    pub cakes: i32,
+    pub pickups: i32,
+    /// Extra points from the multiplier
+    pub combo_bonus: i32,
+    pub penalties: i32,
+}
+
+/// A player's combo and multiplier
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Scoring {
+    /// Pickups in a row, each within `COMBO_WINDOW` of the last
+    pub combo: u32,
+    pub multiplier: f32,
+    /// Game time of the last pickup
+    pub last_pickup: Option<Duration>,
+    pub breakdown: ScoreBreakdown,
+}
+
+impl Default for Scoring {
+    fn default() -> Self {
+        Scoring {
+            combo: 0,
+            multiplier: 1.0,
+            last_pickup: None,
+            breakdown: ScoreBreakdown::default(),
+        }
+    }
+}
+
+/// What the points are for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScoreSource {
+    Cake,
+    Pickup,
+}
+
+/// Points to float up from `position`, sent whenever a score changes
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ScorePopup {
+    pub position: Vec3,
+    pub points: i32,
+}
+
+impl Player {
+    /// Score `base` points for something picked up at game time `now`, returns the points
+    /// after the multiplier. Quick pickups build a combo that raises the multiplier.
+    pub fn award(&mut self, source: ScoreSource, base: i32, now: Duration) -> i32 {
+        let scoring = &mut self.scoring;
+        let quick = scoring
+            .last_pickup
+            .is_some_and(|last| now.saturating_sub(last) <= COMBO_WINDOW);
+
+        if quick {
+            scoring.combo += 1;
+            scoring.multiplier = (scoring.multiplier + MULTIPLIER_STEP).min(MAX_MULTIPLIER);
+        } else {
+            scoring.combo = 1;
+        }
+        scoring.last_pickup = Some(now);
+
+        let points = (base as f32 * scoring.multiplier).round() as i32;
+        match source {
+            ScoreSource::Cake => scoring.breakdown.cakes += base,
+            ScoreSource::Pickup => scoring.breakdown.pickups += base,
+        }
+        scoring.breakdown.combo_bonus += points - base;
+
+        self.score += points;
+        points
+    }
+
+    /// Take points away, which also breaks the combo
+    pub fn penalise(&mut self, points: i32) {
+        self.score -= points;
+        self.scoring.breakdown.penalties += points;
+        self.scoring.combo = 0;
+        self.scoring.multiplier = 1.0;
+    }
+}
+
+/// Where a player's points came from
+pub fn score_breakdown(player: &Player) -> String {
+    let breakdown = &player.scoring.breakdown;
+    format!(
+        "cakes {} + pickups {} + combos {} - penalties {}",
+        breakdown.cakes, breakdown.pickups, breakdown.combo_bonus, breakdown.penalties
+    )
+}
+
+// runs once per gameplay tick, combos end when the window passes and the multiplier sinks
+pub fn decay_multipliers(mut game: ResMut<Game>, time: Res<Time>) {
+    let now = game.elapsed;
+
+    for player in game.players.iter_mut() {
+        let scoring = &mut player.scoring;
+
+        if scoring
+            .last_pickup
+            .is_some_and(|last| now.saturating_sub(last) > COMBO_WINDOW)
+        {
+            scoring.combo = 0;
+        }
+        scoring.multiplier =
+            (scoring.multiplier - MULTIPLIER_DECAY * time.delta_seconds()).max(1.0);
+    }
+}
+
+/// Tags a floating score, `position` is where in the world it started
+#[derive(Component)]
+pub struct ScorePopupText {
+    position: Vec3,
+    age: f32,
+}
+
+pub fn spawn_score_popups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut popups: EventReader<ScorePopup>,
+) {
+    for popup in popups.read() {
+        let (text, color) = if popup.points >= 0 {
+            (format!("+{}", popup.points), Color::rgb(1.0, 0.9, 0.2))
+        } else {
+            (popup.points.to_string(), Color::rgb(1.0, 0.2, 0.2))
+        };
+
+        commands.spawn((
+            TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 32.0,
+                    color,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                display: Display::None,
+                ..default()
+            }),
+            ScorePopupText {
+                position: popup.position,
+                age: 0.0,
+            },
+        ));
+    }
+}
+
+// float the popups up and fade them out, placed over the world position in the first view
+// that can see it
+pub fn animate_score_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    cameras: Query<(&Camera, &GlobalTransform), With<TrackingCamera>>,
+    mut popups: Query<(Entity, &mut ScorePopupText, &mut Style, &mut Text)>,
+) {
+    for (entity, mut popup, mut style, mut text) in popups.iter_mut() {
+        popup.age += time.delta_seconds();
+        if popup.age >= POPUP_TIME {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let position = popup.position + Vec3::Y * POPUP_RISE * popup.age / POPUP_TIME;
+        let on_screen = cameras.iter().find_map(|(camera, camera_transform)| {
+            let viewport = camera.logical_viewport_rect()?;
+            let point = camera.world_to_viewport(camera_transform, position)?;
+            let inside = point.cmpge(Vec2::ZERO).all() && point.cmple(viewport.size()).all();
+            inside.then_some(viewport.min + point)
+        });
+
+        match on_screen {
+            Some(point) => {
+                style.display = Display::Flex;
+                style.left = Val::Px(point.x);
+                style.top = Val::Px(point.y);
+            }
+            None => style.display = Display::None,
+        }
+
+        text.sections[0]
+            .style
+            .color
+            .set_a(1.0 - popup.age / POPUP_TIME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy::CONTACT_PENALTY;
+    use crate::test_support::*;
+
+    #[test]
+    fn quick_pickups_build_a_combo() {
+        let mut player = Player::new(0.0, 0.0, None);
+        let second = Duration::from_secs(1);
+
+        assert_eq!(player.award(ScoreSource::Cake, 2, Duration::ZERO), 2);
+        assert_eq!(player.award(ScoreSource::Cake, 2, second), 3);
+        assert_eq!(player.award(ScoreSource::Cake, 2, second * 2), 4);
+        assert_eq!(player.scoring.combo, 3);
+
+        // too slow, the combo starts over but the multiplier hasn't decayed yet
+        assert_eq!(player.award(ScoreSource::Cake, 2, second * 10), 4);
+        assert_eq!(player.scoring.combo, 1);
+
+        player.penalise(CONTACT_PENALTY);
+        assert_eq!(player.scoring.multiplier, 1.0);
+        assert_eq!(player.score, 2 + 3 + 4 + 4 - CONTACT_PENALTY);
+        assert_eq!(
+            player.scoring.breakdown,
+            ScoreBreakdown {
+                cakes: 8,
+                pickups: 0,
+                combo_bonus: 5,
+                penalties: CONTACT_PENALTY,
+            }
+        );
+        assert_eq!(score_breakdown(&player), "cakes 8 + pickups 0 + combos 5 - penalties 3");
+    }
+
+    #[test]
+    fn multiplier_decays_back_to_one() {
+        let mut app = test_app(1);
+        {
+            let mut game = app.world.resource_mut::<Game>();
+            let now = game.elapsed;
+            let player = &mut game.players[0];
+            player.award(ScoreSource::Pickup, 0, now);
+            player.award(ScoreSource::Pickup, 0, now);
+            assert_eq!(player.scoring.multiplier, 1.5);
+        }
+
+        run_frames(&mut app, 30);
+        let scoring = &app.world.resource::<Game>().players[0].scoring;
+        assert!((scoring.multiplier - 1.25).abs() < 0.01, "{}", scoring.multiplier);
+        assert_eq!(scoring.combo, 2);
+
+        // long after the window the combo is gone and the multiplier is back to normal
+        run_frames(&mut app, 200);
+        let scoring = &app.world.resource::<Game>().players[0].scoring;
+        assert_eq!((scoring.combo, scoring.multiplier), (0, 1.0));
+    }
+}
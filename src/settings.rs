@@ -0,0 +1,579 @@
+use std::path::{Path, PathBuf};
+
+use bevy::pbr::{
+    CascadeShadowConfig, CascadeShadowConfigBuilder, DirectionalLightShadowMap,
+    PointLightShadowMap,
+};
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowMode};
+use serde::{Deserialize, Serialize};
+
+use crate::camera_tracking::TrackingCamera;
+use crate::game_state::{BoardSize, Game};
+use crate::lights::{NightLight, ShadowLight};
+
+/// Window sizes offered by the settings menu
+pub const RESOLUTIONS: [(u32, u32); 4] = [(1280, 720), (1600, 900), (1920, 1080), (2560, 1440)];
+
+/// Seconds in a day of the day/night cycle, 0 keeps the lighting as it is
+pub const DAY_LENGTHS: [u32; 5] = [0, 60, 120, 300, 600];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowModeSetting {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl WindowModeSetting {
+    pub const ALL: [WindowModeSetting; 3] = [
+        WindowModeSetting::Windowed,
+        WindowModeSetting::Borderless,
+        WindowModeSetting::Fullscreen,
+    ];
+
+    pub fn window_mode(self) -> WindowMode {
+        match self {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::Borderless => WindowMode::BorderlessFullscreen,
+            WindowModeSetting::Fullscreen => WindowMode::Fullscreen,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShadowQuality {
+    Off,
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl ShadowQuality {
+    pub const ALL: [ShadowQuality; 4] = [
+        ShadowQuality::Off,
+        ShadowQuality::Low,
+        ShadowQuality::Medium,
+        ShadowQuality::High,
+    ];
+
+    /// Shadow map size in texels, medium is bevy's default
+    pub fn map_size(self) -> usize {
+        match self {
+            ShadowQuality::Off | ShadowQuality::Low => 512,
+            ShadowQuality::Medium => 1024,
+            ShadowQuality::High => 2048,
+        }
+    }
+
+    /// Shadow cascades for the sun, more of them keep shadows sharp further from the camera
+    pub fn cascades(self) -> usize {
+        match self {
+            ShadowQuality::Off | ShadowQuality::Low => 1,
+            ShadowQuality::Medium => 2,
+            ShadowQuality::High => 4,
+        }
+    }
+}
+
+/// Sun shadow cascades reaching from the camera across the whole board, the first one ends just
+/// past the alien the camera follows
+pub fn shadow_cascades(
+    quality: ShadowQuality,
+    board: BoardSize,
+    camera_distance: f32,
+) -> CascadeShadowConfig {
+    let diagonal = Vec2::new(board.width as f32, board.height as f32).length();
+    let maximum_distance = camera_distance + diagonal;
+
+    CascadeShadowConfigBuilder {
+        num_cascades: quality.cascades(),
+        minimum_distance: 0.1,
+        first_cascade_far_bound: (camera_distance + 2.0).min(maximum_distance),
+        maximum_distance,
+        ..default()
+    }
+    .build()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// How fast the cake circles the board, in radians per second
+    pub fn cake_speed(self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.25,
+            Difficulty::Normal => 0.4,
+            Difficulty::Hard => 0.6,
+        }
+    }
+
+    /// How many hostile creatures roam the board
+    pub fn enemy_count(self) -> usize {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 2,
+            Difficulty::Hard => 3,
+        }
+    }
+}
+
+/// How the cake gets around the board
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CakeMovement {
+    /// Stays where it is put
+    Stationary,
+    /// Circles the board
+    #[default]
+    Orbit,
+    /// Walks a loop between points near the corners of the board
+    Patrol,
+    /// Walks away from the nearest alien
+    Flee,
+    /// Walks to random spots
+    Wander,
+}
+
+impl CakeMovement {
+    pub const ALL: [CakeMovement; 5] = [
+        CakeMovement::Stationary,
+        CakeMovement::Orbit,
+        CakeMovement::Patrol,
+        CakeMovement::Flee,
+        CakeMovement::Wander,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct WindowSettings {
+    pub mode: WindowModeSetting,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings {
+            mode: WindowModeSetting::Windowed,
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub shadow_quality: ShadowQuality,
+    /// Seconds from one noon to the next, 0 turns the day/night cycle off
+    pub day_length: u32,
+    /// Whether the lights on the aliens and the cake cast shadows too
+    pub carried_light_shadows: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GameplaySettings {
+    pub difficulty: Difficulty,
+    pub cake_movement: CakeMovement,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct InputSettings {
+    /// Multiplies mouse movement when orbiting and panning the camera
+    pub mouse_sensitivity: f32,
+    /// Multiplies the scroll wheel when zooming the camera
+    pub zoom_sensitivity: f32,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        InputSettings {
+            mouse_sensitivity: 1.0,
+            zoom_sensitivity: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// From 0 (off) to 1
+    pub music_volume: f32,
+    pub effects_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            music_volume: 0.5,
+            effects_volume: 0.8,
+        }
+    }
+}
+
+/// Player options, read from the settings file at startup and written back by the settings
+/// menu. Missing fields take their defaults so older files keep working.
+#[derive(Resource, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub window: WindowSettings,
+    pub graphics: GraphicsSettings,
+    pub gameplay: GameplaySettings,
+    pub board: BoardSize,
+    pub input: InputSettings,
+    pub audio: AudioSettings,
+}
+
+impl Settings {
+    /// Reads the settings, falling back to the defaults when the file is missing or broken
+    pub fn load(path: &Path) -> Settings {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                info!("No settings at {}, using defaults", path.display());
+                return Settings::default();
+            }
+            Err(error) => {
+                warn!("Failed to read settings {}: {error}", path.display());
+                return Settings::default();
+            }
+        };
+
+        match ron::from_str::<Settings>(&text) {
+            Ok(mut settings) => {
+                settings.clamp();
+                settings
+            }
+            Err(error) => {
+                warn!("Invalid settings {}: {error}, using defaults", path.display());
+                Settings::default()
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|error| format!("failed to create {}: {error}", dir.display()))?;
+        }
+
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())?;
+        std::fs::write(path, text)
+            .map_err(|error| format!("failed to write settings {}: {error}", path.display()))
+    }
+
+    // keep hand edited values usable
+    fn clamp(&mut self) {
+        self.window.width = self.window.width.max(640);
+        self.window.height = self.window.height.max(360);
+        self.board = self.board.clamped();
+        self.input.mouse_sensitivity = self.input.mouse_sensitivity.clamp(0.1, 5.0);
+        self.input.zoom_sensitivity = self.input.zoom_sensitivity.clamp(0.1, 5.0);
+        self.audio.music_volume = self.audio.music_volume.clamp(0.0, 1.0);
+        self.audio.effects_volume = self.audio.effects_volume.clamp(0.0, 1.0);
+    }
+
+    /// The rows of the settings menu
+    pub fn menu_rows(&self) -> [String; 13] {
+        [
+            format!("Window: {:?}", self.window.mode),
+            format!("Resolution: {} x {}", self.window.width, self.window.height),
+            format!("Shadows: {:?}", self.graphics.shadow_quality),
+            format!("Difficulty: {:?}", self.gameplay.difficulty),
+            format!("Cake: {:?}", self.gameplay.cake_movement),
+            format!("Board width: {} (next round)", self.board.width),
+            format!("Board height: {} (next round)", self.board.height),
+            format!("Mouse sensitivity: {:.1}", self.input.mouse_sensitivity),
+            format!("Zoom sensitivity: {:.1}", self.input.zoom_sensitivity),
+            format!("Music volume: {:.0}%", self.audio.music_volume * 100.0),
+            format!("Effects volume: {:.0}%", self.audio.effects_volume * 100.0),
+            match self.graphics.day_length {
+                0 => "Day/night cycle: off".to_string(),
+                seconds => format!("Day/night cycle: {seconds}s"),
+            },
+            format!(
+                "Alien and cake shadows: {}",
+                if self.graphics.carried_light_shadows { "on" } else { "off" }
+            ),
+        ]
+    }
+
+    /// Changes the option on menu row `row`, `step` is 1 or -1
+    pub fn change(&mut self, row: usize, step: isize) {
+        match row {
+            0 => self.window.mode = cycle(&WindowModeSetting::ALL, self.window.mode, step),
+            1 => {
+                let current = (self.window.width, self.window.height);
+                (self.window.width, self.window.height) = cycle(&RESOLUTIONS, current, step);
+            }
+            2 => {
+                self.graphics.shadow_quality =
+                    cycle(&ShadowQuality::ALL, self.graphics.shadow_quality, step)
+            }
+            3 => {
+                self.gameplay.difficulty = cycle(&Difficulty::ALL, self.gameplay.difficulty, step)
+            }
+            4 => {
+                self.gameplay.cake_movement =
+                    cycle(&CakeMovement::ALL, self.gameplay.cake_movement, step)
+            }
+            5 => self.board.width = self.board.width.saturating_add_signed(step),
+            6 => self.board.height = self.board.height.saturating_add_signed(step),
+            7 => self.input.mouse_sensitivity += step as f32 * 0.1,
+            8 => self.input.zoom_sensitivity += step as f32 * 0.1,
+            9 => self.audio.music_volume += step as f32 * 0.1,
+            10 => self.audio.effects_volume += step as f32 * 0.1,
+            11 => {
+                self.graphics.day_length =
+                    cycle(&DAY_LENGTHS, self.graphics.day_length, step)
+            }
+            12 => self.graphics.carried_light_shadows = !self.graphics.carried_light_shadows,
+            _ => {}
+        }
+        self.clamp();
+    }
+}
+
+// the next or previous value, wrapping around, values not in `all` go to the first
+fn cycle<T: Copy + PartialEq>(all: &[T], value: T, step: isize) -> T {
+    match all.iter().position(|candidate| *candidate == value) {
+        Some(index) => all[(index as isize + step).rem_euclid(all.len() as isize) as usize],
+        None => all[0],
+    }
+}
+
+/// Where the settings are kept
+#[derive(Resource)]
+pub struct SettingsFile(pub PathBuf);
+
+impl Default for SettingsFile {
+    // the user's config directory, e.g. ~/.config/alien_explorer on Linux
+    fn default() -> Self {
+        let dir = dirs::config_dir()
+            .map(|dir| dir.join("alien_explorer"))
+            .unwrap_or_default();
+        SettingsFile(dir.join("settings.ron"))
+    }
+}
+
+/// The primary window as the settings describe it
+pub fn primary_window(settings: &Settings) -> Window {
+    Window {
+        title: "Alien Explorer".to_string(),
+        mode: settings.window.mode.window_mode(),
+        resolution: (settings.window.width as f32, settings.window.height as f32).into(),
+        ..default()
+    }
+}
+
+pub fn apply_window_settings(
+    settings: Res<Settings>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    let Ok(mut window) = window_query.get_single_mut() else {
+        return;
+    };
+
+    window.mode = settings.window.mode.window_mode();
+    window
+        .resolution
+        .set(settings.window.width as f32, settings.window.height as f32);
+}
+
+pub fn apply_shadow_settings(
+    settings: Res<Settings>,
+    mut point_shadow_map: ResMut<PointLightShadowMap>,
+    mut directional_shadow_map: ResMut<DirectionalLightShadowMap>,
+    mut lights: Query<&mut PointLight, With<ShadowLight>>,
+) {
+    let quality = settings.graphics.shadow_quality;
+
+    point_shadow_map.size = quality.map_size();
+    directional_shadow_map.size = quality.map_size();
+
+    for mut light in lights.iter_mut() {
+        light.shadows_enabled = quality != ShadowQuality::Off;
+    }
+}
+
+// the lights on the aliens and the cake, including ones spawned since the settings changed
+pub fn apply_carried_light_shadows(
+    settings: Res<Settings>,
+    mut lights: Query<(&mut PointLight, Ref<NightLight>)>,
+) {
+    let enabled = settings.graphics.carried_light_shadows
+        && settings.graphics.shadow_quality != ShadowQuality::Off;
+
+    for (mut light, night_light) in lights.iter_mut() {
+        if settings.is_changed() || night_light.is_added() {
+            light.shadows_enabled = enabled;
+        }
+    }
+}
+
+// fit the sun's shadows to the board and to how far out the cameras are zoomed
+pub fn update_shadow_cascades(
+    settings: Res<Settings>,
+    game: Res<Game>,
+    cameras: Query<&TrackingCamera>,
+    mut suns: Query<(&mut DirectionalLight, &mut CascadeShadowConfig)>,
+) {
+    let quality = settings.graphics.shadow_quality;
+    let camera_distance = cameras.iter().map(|camera| camera.radius).fold(0.0, f32::max);
+    let cascades = shadow_cascades(quality, game.board.size(), camera_distance);
+
+    for (mut light, mut config) in suns.iter_mut() {
+        let enabled = quality != ShadowQuality::Off;
+        if light.shadows_enabled != enabled {
+            light.shadows_enabled = enabled;
+        }
+        if config.bounds != cascades.bounds {
+            *config = cascades.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::lights::spawn_lights;
+    use crate::test_support::*;
+
+    #[test]
+    fn settings_fill_in_missing_and_broken_values() {
+        let dir = std::env::temp_dir().join("alien_explorer_test_settings");
+        std::fs::remove_dir_all(&dir).ok();
+        let path = dir.join("settings.ron");
+
+        assert_eq!(Settings::load(&path), Settings::default());
+
+        // older or hand written files may only set some options
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            &path,
+            "(gameplay: (difficulty: Hard), input: (mouse_sensitivity: 50.0))",
+        )
+        .unwrap();
+        let settings = Settings::load(&path);
+        assert_eq!(settings.gameplay.difficulty, Difficulty::Hard);
+        assert_eq!(settings.input.mouse_sensitivity, 5.0);
+        assert_eq!(settings.window, WindowSettings::default());
+
+        std::fs::write(&path, "(window: (mode: Sideways))").unwrap();
+        assert_eq!(Settings::load(&path), Settings::default());
+
+        let mut settings = Settings::default();
+        settings.change(2, 1);
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), settings);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn settings_menu_cycles_through_options() {
+        let mut settings = Settings::default();
+
+        settings.change(0, -1);
+        assert_eq!(settings.window.mode, WindowModeSetting::Fullscreen);
+        settings.change(0, 1);
+        assert_eq!(settings.window.mode, WindowModeSetting::Windowed);
+
+        settings.change(1, 1);
+        assert_eq!((settings.window.width, settings.window.height), RESOLUTIONS[1]);
+
+        // normal, hard, then around to easy
+        settings.change(3, 1);
+        settings.change(3, 1);
+        assert_eq!(settings.gameplay.difficulty, Difficulty::Easy);
+
+        settings.change(4, -1);
+        assert_eq!(settings.gameplay.cake_movement, CakeMovement::Stationary);
+
+        // the board grows and shrinks a tile at a time, down to the smallest size
+        settings.change(5, 1);
+        assert_eq!(settings.board.width, 15);
+        for _ in 0..30 {
+            settings.change(6, -1);
+        }
+        assert_eq!(settings.board.height, 3);
+        assert_eq!(settings.menu_rows()[6], "Board height: 3 (next round)");
+
+        // volumes stop at full
+        settings.change(10, 1);
+        settings.change(10, 1);
+        settings.change(10, 1);
+        assert_eq!(settings.audio.effects_volume, 1.0);
+        assert_eq!(settings.menu_rows()[10], "Effects volume: 100%");
+
+        settings.change(12, 1);
+        assert!(settings.graphics.carried_light_shadows);
+        assert_eq!(settings.menu_rows()[12], "Alien and cake shadows: on");
+    }
+
+    #[test]
+    fn shadows_follow_the_board_and_settings() {
+        let small = shadow_cascades(ShadowQuality::Medium, BoardSize::default(), 9.0);
+        let large = shadow_cascades(
+            ShadowQuality::Medium,
+            BoardSize {
+                width: 40,
+                height: 60,
+            },
+            9.0,
+        );
+        assert_eq!(small.bounds.len(), 2);
+        assert!(large.bounds[1] > small.bounds[1]);
+        assert_eq!(shadow_cascades(ShadowQuality::High, BoardSize::default(), 9.0).bounds.len(), 4);
+
+        let mut app = test_app(1);
+        app.add_systems(Update, (apply_carried_light_shadows, update_shadow_cascades));
+        app.world.run_system_once(spawn_lights);
+        let carried_shadows = |app: &mut App| -> Vec<bool> {
+            app.world
+                .query_filtered::<&PointLight, With<NightLight>>()
+                .iter(&app.world)
+                .map(|light| light.shadows_enabled)
+                .collect()
+        };
+
+        // the sun is fitted to the board straight away, there are no cameras to step back for, and
+        // the carried lights start without shadows
+        app.update();
+        let (sun, cascades) = app
+            .world
+            .query::<(&DirectionalLight, &bevy::pbr::CascadeShadowConfig)>()
+            .single(&app.world);
+        assert!(sun.shadows_enabled);
+        let fitted = shadow_cascades(ShadowQuality::Medium, BoardSize::default(), 0.0);
+        assert_eq!(cascades.bounds, fitted.bounds);
+        assert_eq!(carried_shadows(&mut app), [false; 2]);
+
+        app.world.resource_mut::<Settings>().graphics.carried_light_shadows = true;
+        app.update();
+        assert_eq!(carried_shadows(&mut app), [true; 2]);
+
+        // turning shadows off covers every light
+        app.world.resource_mut::<Settings>().graphics.shadow_quality = ShadowQuality::Off;
+        app.update();
+        assert_eq!(carried_shadows(&mut app), [false; 2]);
+        let sun = app.world.query::<&DirectionalLight>().single(&app.world);
+        assert!(!sun.shadows_enabled);
+    }
+}
@@ -1,99 +1,111 @@
-use bevy::pbr::CascadeShadowConfigBuilder;
 use bevy::prelude::*;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use std::f32::consts::PI;
 
+use crate::board::{Board, Tile};
 // use crate::camera_pan_and_orbit::PanOrbitCamera;
 use crate::camera_tracking::TrackingCamera;
+use crate::enemy::*;
 use crate::game_state::*;
+use crate::interpolation::SimulatedTransform;
+use crate::lights::{NightLight, NIGHT_LIGHT_INTENSITY};
+use crate::particles::{ParticleEmitter, ParticleKind};
+use crate::pickup::{PickupKinds, PICKUP_LIFT};
+use crate::save::LoadedSave;
 
 pub fn spawn_camera(mut commands: Commands, game: Res<Game>) {
-    info!("Spawning a controllable 3D perspective camera");
-
-    let look_at = Vec3::new(game.player.i, 1., game.player.j);
+    info!("Spawning a controllable 3D perspective camera for each player");
 
     let translation = Vec3::new(-2.0, 2.5, 5.0);
     let radius = translation.length();
 
-    commands.spawn((
-        Camera3dBundle {
-            transform: Transform::from_translation(translation).looking_at(look_at, Vec3::Y),
-            ..Default::default()
-        },
-        TrackingCamera {
-            radius,
-            ..Default::default()
-        },
-    ));
-}
+    // only locally controlled players get a camera, remote players are watched through their own
+    let local_players = game
+        .players
+        .iter()
+        .enumerate()
+        .filter(|(_, player)| player.controls.is_some());
 
-pub fn _spawn_directional_light(mut commands: Commands) {
-    info!("Spawning directional 'sun' light");
+    for (index, (player_index, player)) in local_players.enumerate() {
+        let look_at = Vec3::new(player.i, 1., player.j);
 
-    commands.spawn(DirectionalLightBundle {
-        directional_light: DirectionalLight {
-            shadows_enabled: true,
-            ..default()
-        },
-        transform: Transform {
-            translation: Vec3::new(0.0, 2.0, 0.0),
-            rotation: Quat::from_rotation_x(-PI / 4.),
-            ..default()
-        },
-        // The default cascade config is designed to handle large scenes.
-        // As this example has a much smaller world, we can tighten the shadow
-        // bounds for better visual quality.
-        cascade_shadow_config: CascadeShadowConfigBuilder {
-            first_cascade_far_bound: 4.0,
-            maximum_distance: 10.0,
-            ..default()
+        let mut camera = commands.spawn((
+            Camera3dBundle {
+                transform: Transform::from_translation(translation).looking_at(look_at, Vec3::Y),
+                camera: Camera {
+                    // later cameras render after the first one and must not clear its viewport
+                    order: index as isize,
+                    clear_color: if index == 0 {
+                        ClearColorConfig::Default
+                    } else {
+                        ClearColorConfig::None
+                    },
+                    ..default()
+                },
+                ..Default::default()
+            },
+            TrackingCamera {
+                player: player_index,
+                radius,
+                ..Default::default()
+            },
+        ));
+
+        // sounds are heard from the first player's view
+        if index == 0 {
+            camera.insert(SpatialListener::new(0.5));
         }
-        .into(),
-        ..default()
-    });
+    }
 }
 
-pub fn spawn_point_light(mut commands: Commands) {
-    info!("Spawning point light");
-
-    commands.spawn(PointLightBundle {
-        transform: Transform::from_xyz(4.0, 10.0, 4.0),
-        point_light: PointLight {
-            intensity: 3000.0,
-            shadows_enabled: true,
-            range: 30.0,
-            ..default()
-        },
-        ..default()
-    });
-}
+/// One tile of the board, despawned when the board is rebuilt at another size
+#[derive(Component)]
+pub struct BoardTile;
 
 pub fn spawn_game_board(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut game: ResMut<Game>,
+    board_seed: Res<BoardSeed>,
+    board_size: Res<BoardSize>,
+    saved: Option<Res<LoadedSave>>,
 ) {
-    info!("Spawning game board");
+    info!(
+        "Spawning {} x {} game board with seed {}",
+        board_size.width, board_size.height, board_seed.0
+    );
+
+    // a loaded save keeps its exact heights
+    game.board = match &saved {
+        Some(saved) => Board::new(*board_size, |cell| Tile::new(saved.0.board[cell.j][cell.i])),
+        None => random_board(*board_size, board_seed.0),
+    };
+
+    spawn_board_tiles(&mut commands, &asset_server, &game.board);
+}
 
-    // spawn the game board
+/// A board of `size` with random heights, the same ones for the same `seed`
+pub fn random_board(size: BoardSize, seed: u64) -> Board {
+    let mut rng = StdRng::seed_from_u64(seed);
+    Board::new(size, |_| Tile::new(rng.gen_range(-0.1..0.1)))
+}
+
+/// Spawn the tile scene of every cell on `board`
+pub fn spawn_board_tiles(commands: &mut Commands, asset_server: &AssetServer, board: &Board) {
     let tile_scene = asset_server.load("models/tile.glb#Scene0");
 
-    game.board = (0..BOARD_SIZE_J.round() as i32)
-        .map(|j| {
-            (0..BOARD_SIZE_I.round() as i32)
-                .map(|i| {
-                    let height = rand::thread_rng().gen_range(-0.1..0.1);
-                    commands.spawn(SceneBundle {
-                        transform: Transform::from_xyz(i as f32, height - 0.2, j as f32),
-                        scene: tile_scene.clone(),
-                        ..default()
-                    });
-                    Cell { height }
-                })
-                .collect()
-        })
-        .collect();
+    for cell in board.cells() {
+        commands.spawn((
+            SceneBundle {
+                transform: Transform::from_translation(board.cell_to_world(cell) - Vec3::Y * 0.2),
+                scene: tile_scene.clone(),
+                ..default()
+            },
+            BoardTile,
+        ));
+    }
 }
 
 pub fn spawn_character(
@@ -101,8 +113,21 @@ pub fn spawn_character(
     asset_server: Res<AssetServer>,
     mut game: ResMut<Game>,
 ) {
-    info!("Spawning character");
+    info!("Spawning characters");
+
+    for index in 0..game.players.len() {
+        let entity = spawn_player_entity(&mut commands, &asset_server, &game, index);
+        game.players[index].entity = Some(entity);
+    }
+}
 
+/// Spawn the alien scene for `game.players[index]`, also used when players join over the network
+pub fn spawn_player_entity(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    game: &Game,
+    index: usize,
+) -> Entity {
     //scene: asset_server.load("models/alien.glb#Scene0"),
     // scene: asset_server.load("/Users/john/Dev_Assets/glTF-Sample-Models/2.0/CesiumMan/glTF/CesiumMan.gltf#Scene0"),
 
@@ -111,129 +136,169 @@ pub fn spawn_character(
         "models/alien.glb#Scene0"
     );
 
-    game.player.entity = Some(
-        commands
-            .spawn(SceneBundle {
-                transform: Transform {
-                    translation: Vec3::new(
-                        game.player.i,
-                        game.board[game.player.j.round() as usize][game.player.i.round() as usize]
-                            .height,
-                        game.player.j,
-                    ),
-                    rotation: Quat::from_rotation_y(-PI / 0.5),
-                    ..default()
-                },
-                scene: character_asset,
-                ..default()
-            })
-            .with_children(|children| {
-                children.spawn(PointLightBundle {
-                    point_light: PointLight {
-                        color: Color::rgb(1.0, 1.0, 0.0),
-                        intensity: 1000.0,
-                        range: 10.0,
-                        ..default()
-                    },
-                    transform: Transform::from_xyz(0.0, 2.0, 0.0),
-                    ..default()
-                });
-            })
-            .id(),
-    );
-}
+    let player = &game.players[index];
+    let position = Vec3::new(player.i, 0.0, player.j);
 
-pub fn spawn_cake(mut commands: Commands, asset_server: Res<AssetServer>, mut game: ResMut<Game>) {
-    info!("Spawning cake");
-
-    game.cake.handle = asset_server.load("models/cakeBirthday.glb#Scene0");
-
-    game.cake.entity = Some(
-        commands
-            .spawn(SceneBundle {
-                transform: Transform::from_xyz(
-                    game.cake.i,
-                    game.board[game.cake.j.round() as usize][game.cake.i.round() as usize].height
-                        + 0.2,
-                    game.cake.j,
-                ),
-                scene: game.cake.handle.clone(),
+    let transform = Transform {
+        translation: Vec3::new(player.i, game.board.height_at(position), player.j),
+        rotation: Quat::from_rotation_y(-PI / 0.5),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            SceneBundle {
+                transform,
+                scene: character_asset,
                 ..default()
-            })
-            .with_children(|children| {
-                children.spawn(PointLightBundle {
+            },
+            SimulatedTransform::new(transform),
+            // dust under the feet while walking
+            ParticleEmitter::new(ParticleKind::Dust, 15.0).when_moving(),
+        ))
+        .with_children(|children| {
+            children.spawn((
+                PointLightBundle {
                     point_light: PointLight {
                         color: Color::rgb(1.0, 1.0, 0.0),
-                        intensity: 1000.0,
+                        intensity: NIGHT_LIGHT_INTENSITY,
                         range: 10.0,
                         ..default()
                     },
                     transform: Transform::from_xyz(0.0, 2.0, 0.0),
                     ..default()
-                });
-            })
-            .id(),
-    );
+                },
+                NightLight,
+            ));
+        })
+        .id()
 }
 
-pub fn _spawn_cake_two(
-    time: Res<Time>,
-    mut timer: ResMut<CakeSpawnTimer>,
-    // mut next_state: ResMut<NextState<GameState>>,
+pub fn spawn_enemies(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     mut game: ResMut<Game>,
+    enemy_count: Res<EnemyCount>,
+    board_seed: Res<BoardSeed>,
+    saved: Option<Res<LoadedSave>>,
 ) {
-    info!("Spawning random cake");
+    commands.insert_resource(EnemyRng(StdRng::seed_from_u64(board_seed.0)));
 
-    // make sure we wait enough time before spawning the next Cake
-    if !timer.0.tick(time.delta()).finished() {
-        return;
+    // a loaded save brings its own creatures, older saves without any get new ones
+    let saved_enemies = saved.is_some_and(|saved| !saved.0.enemies.is_empty());
+    if !saved_enemies {
+        game.enemies = (0..enemy_count.0)
+            .map(|index| enemy_start(index, &game.board))
+            .collect();
     }
+    info!("Spawning {} creature(s)", game.enemies.len());
 
-    if let Some(entity) = game.cake.entity {
-        game.score -= 3;
-        commands.entity(entity).despawn_recursive();
-        game.cake.entity = None;
-        // if game.score <= -5 {
-        //     next_state.set(GameState::GameOver);
-        //     return;
-        // }
+    for index in 0..game.enemies.len() {
+        let entity = spawn_enemy_entity(&mut commands, &asset_server, &game, index);
+        game.enemies[index].entity = Some(entity);
     }
+}
 
-    // ensure Cake doesn't spawn on the player
-    loop {
-        game.cake.i = rand::thread_rng().gen_range(0..BOARD_SIZE_I.round() as usize) as f32;
-        game.cake.j = rand::thread_rng().gen_range(0..BOARD_SIZE_J.round() as usize) as f32;
-        if game.cake.i != game.player.i || game.cake.j != game.player.j {
-            break;
-        }
-    }
-    game.cake.entity = Some(
-        commands
-            .spawn(SceneBundle {
-                transform: Transform::from_xyz(
-                    game.cake.i,
-                    game.board[game.cake.j.round() as usize][game.cake.i.round() as usize].height
-                        + 0.2,
-                    game.cake.j,
-                ),
-                scene: game.cake.handle.clone(),
+/// Spawn the scene for `game.enemies[index]`, a smaller alien under a red light
+pub fn spawn_enemy_entity(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    game: &Game,
+    index: usize,
+) -> Entity {
+    let enemy = &game.enemies[index];
+
+    let transform = Transform {
+        translation: Vec3::new(enemy.i, game.board.height_at(enemy.position()), enemy.j),
+        rotation: Quat::from_rotation_y(enemy.rotation),
+        scale: Vec3::splat(0.7),
+    };
+
+    commands
+        .spawn((
+            SceneBundle {
+                transform,
+                scene: asset_server.load("models/alien.glb#Scene0"),
                 ..default()
-            })
-            .with_children(|children| {
-                children.spawn(PointLightBundle {
+            },
+            SimulatedTransform::new(transform),
+        ))
+        .with_children(|children| {
+            children.spawn(PointLightBundle {
+                point_light: PointLight {
+                    color: Color::rgb(1.0, 0.1, 0.1),
+                    intensity: 1000.0,
+                    range: 6.0,
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 2.0, 0.0),
+                ..default()
+            });
+        })
+        .id()
+}
+
+/// Spawn the scene for `game.pickups[index]` with the model, light and sparkles its kind asks for
+pub fn spawn_pickup_entity(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    game: &Game,
+    kinds: &PickupKinds,
+    index: usize,
+) -> Entity {
+    let pickup = &game.pickups[index];
+    let kind = &kinds.0[pickup.kind];
+    let (red, green, blue) = kind.light;
+    let transform = Transform::from_xyz(
+        pickup.i,
+        game.board.height_at(pickup.position()) + PICKUP_LIFT,
+        pickup.j,
+    )
+    .with_scale(Vec3::splat(kind.scale));
+
+    let mut entity = commands.spawn((
+        SceneBundle {
+            transform,
+            scene: asset_server.load(&kind.model),
+            ..default()
+        },
+        SimulatedTransform::new(transform),
+    ));
+
+    if kind.sparkles {
+        entity.insert(ParticleEmitter::new(ParticleKind::Sparkle, 8.0));
+    }
+
+    entity.with_children(|children| {
+        let color = Color::rgb(red, green, blue);
+        if kind.night_light {
+            children.spawn((
+                PointLightBundle {
                     point_light: PointLight {
-                        color: Color::rgb(1.0, 1.0, 0.0),
-                        intensity: 1000.0,
+                        color,
+                        intensity: NIGHT_LIGHT_INTENSITY,
                         range: 10.0,
                         ..default()
                     },
                     transform: Transform::from_xyz(0.0, 2.0, 0.0),
                     ..default()
-                });
-            })
-            .id(),
-    );
+                },
+                NightLight,
+            ));
+        } else {
+            children.spawn(PointLightBundle {
+                point_light: PointLight {
+                    color,
+                    intensity: 500.0,
+                    range: 4.0,
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 1.5, 0.0),
+                ..default()
+            });
+        }
+    });
+    entity.id()
 }
 
 pub fn spawn_scoreboard(mut commands: Commands, asset_server: Res<AssetServer>) {
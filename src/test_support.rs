@@ -0,0 +1,117 @@
+//! Fixtures shared by the tests in each module
+
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::add_gameplay_systems;
+use crate::board::*;
+use crate::game_state::*;
+use crate::pickup::*;
+use crate::picking::CursorPosition;
+use crate::player::*;
+use crate::settings::*;
+
+// distance the alien walks in one gameplay tick
+pub const MOVE_STEP: f32 = PLAYER_SPEED / 60.0;
+
+/// The gameplay systems from `main.rs` on top of headless plugins, with keyboard input and
+/// every frame lasting exactly one gameplay tick so tests can press keys and step time by hand
+pub fn test_app(player_count: usize) -> App {
+    let mut app = test_app_without_startup(player_count);
+
+    // run the startup systems so the board and characters exist
+    app.update();
+    app
+}
+
+// for tests that add resources the startup systems read
+pub fn test_app_without_startup(player_count: usize) -> App {
+    let mut app = App::new();
+
+    app.add_plugins((
+        MinimalPlugins,
+        bevy::transform::TransformPlugin,
+        bevy::hierarchy::HierarchyPlugin,
+        AssetPlugin::default(),
+        bevy::scene::ScenePlugin,
+    ))
+    .insert_resource(TimeUpdateStrategy::ManualDuration(TICK_DURATION))
+    .init_resource::<ButtonInput<KeyCode>>()
+    .init_resource::<ButtonInput<MouseButton>>()
+    .add_event::<MouseMotion>()
+    .add_event::<MouseWheel>()
+    .init_resource::<CursorPosition>()
+    .insert_resource(PlayerCount(player_count))
+    .insert_resource(BoardSeed(1))
+    .add_systems(PreUpdate, read_keyboard_input);
+
+    add_gameplay_systems(&mut app);
+    app
+}
+
+pub fn run_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+    }
+}
+
+pub fn press(app: &mut App, key: KeyCode) {
+    app.world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+}
+
+pub fn release_all(app: &mut App) {
+    app.world.resource_mut::<ButtonInput<KeyCode>>().release_all();
+}
+
+pub fn player_position(app: &App, index: usize) -> (f32, f32) {
+    let player = &app.world.resource::<Game>().players[index];
+    (player.i, player.j)
+}
+
+// 5 x 5 board with a wall along j = 2 except for a gap at i = 4, and a cliff at (0, 4)
+pub fn walled_board() -> Board {
+    Board::new(
+        BoardSize {
+            width: 5,
+            height: 5,
+        },
+        |cell| Tile {
+            height: if cell == Cell::new(0, 4) { 1.0 } else { 0.0 },
+            walkable: cell.j != 2 || cell.i == 4,
+        },
+    )
+}
+
+pub fn pickup_kind(kinds: &PickupKinds, name: &str) -> usize {
+    kinds.0.iter().position(|kind| kind.name == name).unwrap()
+}
+
+pub fn cake_app(movement: CakeMovement) -> App {
+    let mut app = test_app(1);
+    app.world.resource_mut::<Settings>().gameplay.cake_movement = movement;
+    app
+}
+
+// the cake on the board, `None` while it is eaten
+pub fn cake(app: &App) -> Option<&Pickup> {
+    let kind = pickup_kind(app.world.resource::<PickupKinds>(), "cake");
+    app.world
+        .resource::<Game>()
+        .pickups
+        .iter()
+        .find(|pickup| pickup.kind == kind)
+}
+
+pub fn cake_position(app: &App) -> (f32, f32) {
+    let cake = cake(app).expect("cake should be on the board");
+    (cake.i, cake.j)
+}
+
+pub fn move_cake(app: &mut App, i: f32, j: f32) {
+    let kind = pickup_kind(app.world.resource::<PickupKinds>(), "cake");
+    let mut game = app.world.resource_mut::<Game>();
+    let cake = game.pickups.iter_mut().find(|pickup| pickup.kind == kind).unwrap();
+    cake.i = i;
+    cake.j = j;
+}
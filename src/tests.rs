@@ -0,0 +1,214 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::board::*;
+use crate::game_state::*;
+use crate::interpolation::SimulatedTransform;
+use crate::pickup::*;
+use crate::player::*;
+use crate::menu::restart_game;
+use crate::save::*;
+use crate::spawn::BoardTile;
+use crate::test_support::*;
+
+#[test]
+fn startup_builds_board_and_characters() {
+    let app = test_app(2);
+    let game = app.world.resource::<Game>();
+    let board_size = BoardSize::default();
+
+    assert_eq!(game.board.size(), board_size);
+    assert_eq!(game.board.cells().count(), board_size.width * board_size.height);
+    assert_eq!(game.players.len(), 2);
+    assert!(game.players.iter().all(|player| player.entity.is_some()));
+    assert!(cake(&app).is_some_and(|cake| cake.entity.is_some()));
+}
+
+#[test]
+fn arrow_keys_move_the_player() {
+    let mut app = test_app(1);
+    let (start_i, start_j) = player_position(&app, 0);
+
+    press(&mut app, KeyCode::ArrowUp);
+    run_frames(&mut app, 10);
+
+    let (i, j) = player_position(&app, 0);
+    assert!(i > start_i + 0.5, "player should have moved up from {start_i} to {i}");
+    assert_eq!(j, start_j);
+
+    let game = app.world.resource::<Game>();
+    let player = &game.players[0];
+    assert_eq!(player.rotation, -PI / 2.);
+
+    let simulated = app.world.get::<SimulatedTransform>(player.entity.unwrap()).unwrap();
+    assert_eq!(simulated.current.translation.x, player.i);
+    assert_eq!(simulated.current.translation.z, player.j);
+}
+
+#[test]
+fn player_stays_on_the_board() {
+    let mut app = test_app(1);
+    let board_size = BoardSize::default();
+
+    press(&mut app, KeyCode::ArrowUp);
+    press(&mut app, KeyCode::ArrowRight);
+    run_frames(&mut app, 300);
+
+    let (i, j) = player_position(&app, 0);
+    assert!(i < board_size.max_i() + MOVE_STEP, "i = {i}");
+    assert!(j < board_size.max_j() + MOVE_STEP, "j = {j}");
+    assert!(i > board_size.max_i() - MOVE_STEP, "i = {i}");
+
+    release_all(&mut app);
+    press(&mut app, KeyCode::ArrowDown);
+    press(&mut app, KeyCode::ArrowLeft);
+    run_frames(&mut app, 300);
+
+    let (i, j) = player_position(&app, 0);
+    assert!(i > -MOVE_STEP, "i = {i}");
+    assert!(j > -MOVE_STEP, "j = {j}");
+    assert!(i < MOVE_STEP, "i = {i}");
+}
+
+#[test]
+fn each_player_has_their_own_keys() {
+    let mut app = test_app(2);
+    let first_start = player_position(&app, 0);
+    let second_start = player_position(&app, 1);
+
+    press(&mut app, KeyCode::KeyW);
+    run_frames(&mut app, 10);
+
+    assert_eq!(player_position(&app, 0), first_start);
+    assert!(player_position(&app, 1).0 > second_start.0);
+}
+
+#[test]
+fn eating_the_cake_scores_points() {
+    let mut app = test_app(2);
+
+    let (i, j) = player_position(&app, 1);
+    move_cake(&mut app, i, j);
+    let cake_entity = cake(&app).unwrap().entity.unwrap();
+
+    app.world.run_system_once(collect_pickups);
+
+    let game = app.world.resource::<Game>();
+    assert_eq!(game.players[1].score, 2);
+    assert_eq!(game.players[1].cake_eaten, 1);
+    assert_eq!(game.players[0].score, 0);
+    assert!(cake(&app).is_none());
+    assert!(app.world.get_entity(cake_entity).is_none());
+    assert_eq!(app.world.resource::<Events<CakeEaten>>().len(), 1);
+}
+
+#[test]
+fn players_stop_moving_after_game_over() {
+    let mut app = test_app(1);
+
+    app.world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::GameOver);
+    app.update();
+    assert_eq!(*app.world.resource::<State<GameState>>().get(), GameState::GameOver);
+
+    let start = player_position(&app, 0);
+    press(&mut app, KeyCode::ArrowUp);
+    run_frames(&mut app, 10);
+
+    assert_eq!(player_position(&app, 0), start);
+}
+
+#[test]
+fn movement_follows_ticks_not_frames() {
+    let mut app = test_app(1);
+    let (start_i, _) = player_position(&app, 0);
+
+    // frames half a tick long: twenty frames are ten ticks
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(TICK_DURATION / 2));
+    press(&mut app, KeyCode::ArrowUp);
+    run_frames(&mut app, 20);
+
+    let (i, _) = player_position(&app, 0);
+    assert!((i - (start_i + 10.0 * MOVE_STEP)).abs() < 0.001, "i = {i}");
+
+    // the rendered alien sits between the last two ticks
+    let game = app.world.resource::<Game>();
+    let entity = game.players[0].entity.unwrap();
+    let simulated = app.world.get::<SimulatedTransform>(entity).unwrap();
+    let transform = app.world.get::<Transform>(entity).unwrap();
+    assert!(transform.translation.x >= simulated.previous.translation.x);
+    assert!(transform.translation.x <= simulated.current.translation.x);
+}
+
+#[test]
+fn round_ends_in_game_over() {
+    let mut app = test_app(1);
+    app.insert_resource(RoundLength(TICK_DURATION * 5));
+
+    run_frames(&mut app, 4);
+    assert_eq!(*app.world.resource::<State<GameState>>().get(), GameState::Playing);
+
+    run_frames(&mut app, 2);
+    assert_eq!(*app.world.resource::<State<GameState>>().get(), GameState::GameOver);
+}
+
+#[test]
+fn board_size_is_set_at_runtime() {
+    let board_size = BoardSize {
+        width: 6,
+        height: 9,
+    };
+    let mut app = test_app_without_startup(1);
+    app.insert_resource(board_size);
+    app.update();
+
+    let game = app.world.resource::<Game>();
+    assert_eq!(game.board.size(), board_size);
+    assert_eq!(game.board.rows().count(), 9);
+    assert!(game.board.rows().all(|row| row.len() == 6));
+    assert_eq!(player_position(&app, 0), (3.0, 5.0));
+
+    press(&mut app, KeyCode::ArrowUp);
+    press(&mut app, KeyCode::ArrowRight);
+    run_frames(&mut app, 120);
+    assert_eq!(player_position(&app, 0), (5.0, 8.0));
+
+    // saves keep the size they were played at
+    let saved = SavedGame::from_game(
+        app.world.resource::<Game>(),
+        app.world.resource::<PickupKinds>(),
+        1,
+        GameMode::default(),
+        None,
+    );
+    assert_eq!(saved.board_size(), board_size);
+    assert_eq!(SavedGame::parse(&ron::to_string(&saved).unwrap()), Ok(saved));
+}
+
+#[test]
+fn new_board_size_is_built_on_restart() {
+    let mut app = test_app(1);
+    let board_size = BoardSize {
+        width: 8,
+        height: 5,
+    };
+    app.insert_resource(board_size);
+    app.world.run_system_once(restart_game);
+    app.update();
+
+    let game = app.world.resource::<Game>();
+    assert_eq!(game.board.size(), board_size);
+    assert_eq!(player_position(&app, 0), start_position(0, 1, board_size));
+    assert!(game
+        .pickups
+        .iter()
+        .all(|pickup| board_size.contains(pickup.i, pickup.j)));
+
+    let mut tiles = app.world.query_filtered::<(), With<BoardTile>>();
+    assert_eq!(tiles.iter(&app.world).count(), 8 * 5);
+}